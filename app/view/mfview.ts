@@ -693,17 +693,14 @@ const HANDLERS: { [tagName: string]: (node: Element, vc: ViewerConfig) => void |
     },
     [TagNames.Diagram]: async (node) => {
         const diagram = await lazyModules.diagram();
-        const svgCode = diagram.to_svg(node.textContent!);
-        node.innerHTML = svgCode;
 
-        // Make the SVG image responsive
-        const svg = node.firstElementChild as SVGSVGElement;
-        const width = parseFloat(svg.getAttribute('width')!);
-        const height = parseFloat(svg.getAttribute('height')!);
+        // The SVG image only has a `viewBox`, making it responsive
+        const rendered = diagram.render(node.textContent!);
+        const width = rendered.width;
+        node.innerHTML = rendered.svg;
+        rendered.free();
 
-        svg.setAttribute('viewBox', `0 0 ${width} ${height}`);
-        svg.removeAttribute('width');
-        svg.removeAttribute('height');
+        const svg = node.firstElementChild as SVGSVGElement;
 
         const inner = node.ownerDocument!.createElement(ViewTagNames.DiagramInner);
         inner.appendChild(svg);
@@ -4,13 +4,135 @@ use wee_alloc;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 use wasm_bindgen::prelude::*;
+#[cfg(feature = "json")]
+use serde::Serialize;
 use svgbob;
 
-#[wasm_bindgen]
-pub fn to_svg(s: &str) -> String {
+fn settings() -> svgbob::Settings {
     let mut settings = svgbob::Settings::default();
     settings.stroke_width = 1.0;
+    // many diagrams are embedded in the same document
+    settings.id_namespace = svgbob::IdNamespace::ContentHash;
+    // scaled to the width of `<mf-diagram-inner>`
+    settings.sizing = svgbob::Sizing::ViewBox;
+    // no gaps between the arcs and lines of the rounded corners when scaled up
+    settings.merge_paths = true;
+    // pages embed many diagrams
+    settings.compact = true;
+    settings
+}
 
-    let g = svgbob::Grid::from_str(&s, &settings);
+#[wasm_bindgen]
+pub fn to_svg(s: &str) -> String {
+    let g = svgbob::Grid::from_str(&s, &settings());
     format!("{}", g.get_svg())
 }
+
+/// A rendered diagram and its intrinsic size.
+#[wasm_bindgen]
+pub struct Rendered {
+    svg: String,
+    width: f32,
+    height: f32,
+}
+
+#[wasm_bindgen]
+impl Rendered {
+    #[wasm_bindgen(getter)]
+    pub fn svg(&self) -> String {
+        self.svg.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+}
+
+#[wasm_bindgen]
+pub fn render(s: &str) -> Rendered {
+    let g = svgbob::Grid::from_str(&s, &settings());
+    let (svg, view_box) = g.get_svg_with_view_box();
+    Rendered {
+        svg: format!("{}", svg),
+        width: view_box.width,
+        height: view_box.height,
+    }
+}
+
+/// The elements of a diagram and the size of its grid.
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct Geometry {
+    columns: usize,
+    rows: usize,
+    width: f32,
+    height: f32,
+    /// the groups of connected elements, in the coordinates of the SVG
+    elements: Vec<Vec<svgbob::Element>>,
+}
+
+/// The elements of the diagram as JSON, for drawing and hit-testing
+/// without parsing the SVG.
+#[cfg(feature = "json")]
+#[wasm_bindgen]
+pub fn to_json(s: &str) -> String {
+    let g = svgbob::Grid::from_str(&s, &settings());
+    let (width, height) = g.get_size();
+    let geometry = Geometry {
+        columns: g.columns(),
+        rows: g.rows(),
+        width: width,
+        height: height,
+        elements: g.get_elements(),
+    };
+    serde_json::to_string(&geometry).expect("serializing the elements")
+}
+
+/// A diagram being edited, only the rows around an edit are rendered again.
+#[wasm_bindgen]
+pub struct LiveDiagram {
+    grid: svgbob::IncrementalGrid,
+    added_groups: Vec<u32>,
+    removed_groups: Vec<u32>,
+}
+
+#[wasm_bindgen]
+impl LiveDiagram {
+    #[wasm_bindgen(constructor)]
+    pub fn new(s: &str) -> LiveDiagram {
+        LiveDiagram {
+            grid: svgbob::IncrementalGrid::new(s, &settings()),
+            added_groups: vec![],
+            removed_groups: vec![],
+        }
+    }
+
+    pub fn to_svg(&self) -> String {
+        format!("{}", self.grid.get_svg())
+    }
+
+    /// Replace the lines `start..end` with `text` and return the updated SVG.
+    pub fn replace_lines(&mut self, start: usize, end: usize, text: &str) -> String {
+        let update = self.grid.replace_lines(start, end, text);
+        self.added_groups = update.added_groups.iter().map(|&i| i as u32).collect();
+        self.removed_groups = update.removed_groups.iter().map(|&i| i as u32).collect();
+        format!("{}", update.svg)
+    }
+
+    /// The indices of the `<g>` elements added by the last `replace_lines`.
+    pub fn added_groups(&self) -> Vec<u32> {
+        self.added_groups.clone()
+    }
+
+    /// The indices of the `<g>` elements of the previous SVG removed by the
+    /// last `replace_lines`.
+    pub fn removed_groups(&self) -> Vec<u32> {
+        self.removed_groups.clone()
+    }
+}
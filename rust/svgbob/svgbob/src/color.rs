@@ -0,0 +1,377 @@
+/// a color with the components between 0 and 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Rgba {
+    pub(crate) red: f32,
+    pub(crate) green: f32,
+    pub(crate) blue: f32,
+    pub(crate) alpha: f32,
+}
+
+impl Rgba {
+    pub(crate) fn opaque(red: f32, green: f32, blue: f32) -> Rgba {
+        Rgba {
+            red: red,
+            green: green,
+            blue: blue,
+            alpha: 1.0,
+        }
+    }
+
+    /// the opaque color seen when this color is drawn over the opaque `backdrop`
+    pub(crate) fn over(&self, backdrop: &Rgba) -> Rgba {
+        let mix = |c: f32, b: f32| c * self.alpha + b * (1.0 - self.alpha);
+        Rgba::opaque(
+            mix(self.red, backdrop.red),
+            mix(self.green, backdrop.green),
+            mix(self.blue, backdrop.blue),
+        )
+    }
+}
+
+/// a css color the backends can draw: a name, a hex color or an `rgb()`, `rgba()`, `hsl()` or `hsla()` function,
+/// the values are written into the attributes and the style sheet of the svg as they are
+/// so only the characters of these colors are allowed
+pub fn is_color(value: &str) -> bool {
+    let safe = if value.ends_with(')') {
+        let mut function = value[..value.len() - 1].splitn(2, '(');
+        let name = function.next().unwrap_or("");
+        let args = function.next().unwrap_or("");
+        name.chars().all(|c| c.is_ascii_alphabetic()) && args.chars().all(|c| c.is_ascii_digit() || ".,%/-".contains(c))
+    } else {
+        value.chars().all(|c| c.is_ascii_alphanumeric() || c == '#')
+    };
+    safe && parse_color(value).is_some()
+}
+
+/// the css color `value`: a name, a hex color or an `rgb()`, `rgba()`, `hsl()` or `hsla()` function
+/// with the arguments separated by commas or spaces and the alpha optionally after a `/`.
+///
+/// `currentColor` is black, the initial value of the css `color`
+pub(crate) fn parse_color(value: &str) -> Option<Rgba> {
+    let value = value.trim().to_ascii_lowercase();
+    if value.starts_with('#') {
+        return parse_hex(&value[1..]);
+    }
+    if value.ends_with(')') {
+        let mut function = value[..value.len() - 1].splitn(2, '(');
+        let name = function.next().unwrap_or("").trim();
+        let args: Vec<&str> = function
+            .next()?
+            .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        return match name {
+            "rgb" | "rgba" => parse_rgb(&args),
+            "hsl" | "hsla" => parse_hsl(&args),
+            _ => None,
+        };
+    }
+    match value.as_str() {
+        "transparent" => Some(Rgba {
+            alpha: 0.0,
+            ..Rgba::opaque(0.0, 0.0, 0.0)
+        }),
+        "currentcolor" => Some(Rgba::opaque(0.0, 0.0, 0.0)),
+        name => NAMED_COLORS
+            .binary_search_by(|&(named, _)| named.cmp(name))
+            .ok()
+            .and_then(|i| parse_hex(NAMED_COLORS[i].1)),
+    }
+}
+
+/// `rgb`, `rgba`, `rrggbb` or `rrggbbaa` in hex digits
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let components: Vec<f32> = match hex.len() {
+        3 | 4 => hex.chars().filter_map(|c| c.to_digit(16)).map(|v| (v * 17) as f32 / 255.0).collect(),
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .filter_map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .map(|v| v as f32 / 255.0)
+            .collect(),
+        _ => return None,
+    };
+    Some(Rgba {
+        alpha: components.get(3).cloned().unwrap_or(1.0),
+        ..Rgba::opaque(components[0], components[1], components[2])
+    })
+}
+
+/// the red, green and blue from 0 to 255 or as percentages, followed by the optional alpha
+fn parse_rgb(args: &[&str]) -> Option<Rgba> {
+    if args.len() != 3 && args.len() != 4 {
+        return None;
+    }
+    let component = |arg: &str| parse_number(arg, 255.0).map(|v| (v / 255.0).max(0.0).min(1.0));
+    Some(Rgba {
+        red: component(args[0])?,
+        green: component(args[1])?,
+        blue: component(args[2])?,
+        alpha: parse_alpha(args.get(3).cloned())?,
+    })
+}
+
+/// the hue in degrees, the saturation and the lightness as percentages, followed by the optional alpha
+fn parse_hsl(args: &[&str]) -> Option<Rgba> {
+    if args.len() != 3 && args.len() != 4 {
+        return None;
+    }
+    let hue = parse_number(args[0], 360.0)?.rem_euclid(360.0) / 360.0;
+    let fraction = |arg: &str| parse_number(arg, 100.0).map(|v| (v / 100.0).max(0.0).min(1.0));
+    let (saturation, lightness) = (fraction(args[1])?, fraction(args[2])?);
+    let q = if lightness < 0.5 {
+        lightness * (1.0 + saturation)
+    } else {
+        lightness + saturation - lightness * saturation
+    };
+    let p = 2.0 * lightness - q;
+    let channel = |t: f32| {
+        let t = t.rem_euclid(1.0);
+        if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 1.0 / 2.0 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        }
+    };
+    Some(Rgba {
+        alpha: parse_alpha(args.get(3).cloned())?,
+        ..Rgba::opaque(channel(hue + 1.0 / 3.0), channel(hue), channel(hue - 1.0 / 3.0))
+    })
+}
+
+/// the alpha from 0 to 1 or as a percentage, opaque when there is none
+fn parse_alpha(arg: Option<&str>) -> Option<f32> {
+    match arg {
+        Some(arg) => parse_number(arg, 1.0).map(|v| v.max(0.0).min(1.0)),
+        None => Some(1.0),
+    }
+}
+
+/// a finite number, a percentage is a part of `full`
+fn parse_number(arg: &str, full: f32) -> Option<f32> {
+    let (number, scale) = if arg.ends_with('%') {
+        (&arg[..arg.len() - 1], full / 100.0)
+    } else {
+        (arg, 1.0)
+    };
+    match number.parse::<f32>() {
+        Ok(v) if v.is_finite() => Some(v * scale),
+        _ => None,
+    }
+}
+
+/// the css color names with their hex colors, sorted by name
+static NAMED_COLORS: &[(&str, &str)] = &[
+    ("aliceblue", "f0f8ff"),
+    ("antiquewhite", "faebd7"),
+    ("aqua", "00ffff"),
+    ("aquamarine", "7fffd4"),
+    ("azure", "f0ffff"),
+    ("beige", "f5f5dc"),
+    ("bisque", "ffe4c4"),
+    ("black", "000000"),
+    ("blanchedalmond", "ffebcd"),
+    ("blue", "0000ff"),
+    ("blueviolet", "8a2be2"),
+    ("brown", "a52a2a"),
+    ("burlywood", "deb887"),
+    ("cadetblue", "5f9ea0"),
+    ("chartreuse", "7fff00"),
+    ("chocolate", "d2691e"),
+    ("coral", "ff7f50"),
+    ("cornflowerblue", "6495ed"),
+    ("cornsilk", "fff8dc"),
+    ("crimson", "dc143c"),
+    ("cyan", "00ffff"),
+    ("darkblue", "00008b"),
+    ("darkcyan", "008b8b"),
+    ("darkgoldenrod", "b8860b"),
+    ("darkgray", "a9a9a9"),
+    ("darkgreen", "006400"),
+    ("darkgrey", "a9a9a9"),
+    ("darkkhaki", "bdb76b"),
+    ("darkmagenta", "8b008b"),
+    ("darkolivegreen", "556b2f"),
+    ("darkorange", "ff8c00"),
+    ("darkorchid", "9932cc"),
+    ("darkred", "8b0000"),
+    ("darksalmon", "e9967a"),
+    ("darkseagreen", "8fbc8f"),
+    ("darkslateblue", "483d8b"),
+    ("darkslategray", "2f4f4f"),
+    ("darkslategrey", "2f4f4f"),
+    ("darkturquoise", "00ced1"),
+    ("darkviolet", "9400d3"),
+    ("deeppink", "ff1493"),
+    ("deepskyblue", "00bfff"),
+    ("dimgray", "696969"),
+    ("dimgrey", "696969"),
+    ("dodgerblue", "1e90ff"),
+    ("firebrick", "b22222"),
+    ("floralwhite", "fffaf0"),
+    ("forestgreen", "228b22"),
+    ("fuchsia", "ff00ff"),
+    ("gainsboro", "dcdcdc"),
+    ("ghostwhite", "f8f8ff"),
+    ("gold", "ffd700"),
+    ("goldenrod", "daa520"),
+    ("gray", "808080"),
+    ("green", "008000"),
+    ("greenyellow", "adff2f"),
+    ("grey", "808080"),
+    ("honeydew", "f0fff0"),
+    ("hotpink", "ff69b4"),
+    ("indianred", "cd5c5c"),
+    ("indigo", "4b0082"),
+    ("ivory", "fffff0"),
+    ("khaki", "f0e68c"),
+    ("lavender", "e6e6fa"),
+    ("lavenderblush", "fff0f5"),
+    ("lawngreen", "7cfc00"),
+    ("lemonchiffon", "fffacd"),
+    ("lightblue", "add8e6"),
+    ("lightcoral", "f08080"),
+    ("lightcyan", "e0ffff"),
+    ("lightgoldenrodyellow", "fafad2"),
+    ("lightgray", "d3d3d3"),
+    ("lightgreen", "90ee90"),
+    ("lightgrey", "d3d3d3"),
+    ("lightpink", "ffb6c1"),
+    ("lightsalmon", "ffa07a"),
+    ("lightseagreen", "20b2aa"),
+    ("lightskyblue", "87cefa"),
+    ("lightslategray", "778899"),
+    ("lightslategrey", "778899"),
+    ("lightsteelblue", "b0c4de"),
+    ("lightyellow", "ffffe0"),
+    ("lime", "00ff00"),
+    ("limegreen", "32cd32"),
+    ("linen", "faf0e6"),
+    ("magenta", "ff00ff"),
+    ("maroon", "800000"),
+    ("mediumaquamarine", "66cdaa"),
+    ("mediumblue", "0000cd"),
+    ("mediumorchid", "ba55d3"),
+    ("mediumpurple", "9370db"),
+    ("mediumseagreen", "3cb371"),
+    ("mediumslateblue", "7b68ee"),
+    ("mediumspringgreen", "00fa9a"),
+    ("mediumturquoise", "48d1cc"),
+    ("mediumvioletred", "c71585"),
+    ("midnightblue", "191970"),
+    ("mintcream", "f5fffa"),
+    ("mistyrose", "ffe4e1"),
+    ("moccasin", "ffe4b5"),
+    ("navajowhite", "ffdead"),
+    ("navy", "000080"),
+    ("oldlace", "fdf5e6"),
+    ("olive", "808000"),
+    ("olivedrab", "6b8e23"),
+    ("orange", "ffa500"),
+    ("orangered", "ff4500"),
+    ("orchid", "da70d6"),
+    ("palegoldenrod", "eee8aa"),
+    ("palegreen", "98fb98"),
+    ("paleturquoise", "afeeee"),
+    ("palevioletred", "db7093"),
+    ("papayawhip", "ffefd5"),
+    ("peachpuff", "ffdab9"),
+    ("peru", "cd853f"),
+    ("pink", "ffc0cb"),
+    ("plum", "dda0dd"),
+    ("powderblue", "b0e0e6"),
+    ("purple", "800080"),
+    ("rebeccapurple", "663399"),
+    ("red", "ff0000"),
+    ("rosybrown", "bc8f8f"),
+    ("royalblue", "4169e1"),
+    ("saddlebrown", "8b4513"),
+    ("salmon", "fa8072"),
+    ("sandybrown", "f4a460"),
+    ("seagreen", "2e8b57"),
+    ("seashell", "fff5ee"),
+    ("sienna", "a0522d"),
+    ("silver", "c0c0c0"),
+    ("skyblue", "87ceeb"),
+    ("slateblue", "6a5acd"),
+    ("slategray", "708090"),
+    ("slategrey", "708090"),
+    ("snow", "fffafa"),
+    ("springgreen", "00ff7f"),
+    ("steelblue", "4682b4"),
+    ("tan", "d2b48c"),
+    ("teal", "008080"),
+    ("thistle", "d8bfd8"),
+    ("tomato", "ff6347"),
+    ("turquoise", "40e0d0"),
+    ("violet", "ee82ee"),
+    ("wheat", "f5deb3"),
+    ("white", "ffffff"),
+    ("whitesmoke", "f5f5f5"),
+    ("yellow", "ffff00"),
+    ("yellowgreen", "9acd32"),
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the components of the color from 0 to 255
+    fn bytes(color: Option<Rgba>) -> Option<[u8; 4]> {
+        let byte = |v: f32| (v * 255.0).round() as u8;
+        color.map(|c| [byte(c.red), byte(c.green), byte(c.blue), byte(c.alpha)])
+    }
+
+    #[test]
+    fn test_parse_color() {
+        let navy = Some(Rgba::opaque(0.0, 0.0, 128.0 / 255.0));
+        assert_eq!(navy, parse_color("navy"));
+        assert_eq!(navy, parse_color("Navy"));
+        assert_eq!(navy, parse_color("#000080"));
+        assert_eq!(Some(Rgba::opaque(1.0, 0.0, 0.2)), parse_color("#F03"));
+        assert_eq!(Some(Rgba::opaque(1.0, 0.5, 0.0)), parse_color("rgb(100%,127.5,0)"));
+        assert_eq!(Some(0.5), parse_color("rgba(0,0,255,0.5)").map(|c| c.alpha));
+        assert_eq!(Some(0.5), parse_color("rgb(0 0 255 / 50%)").map(|c| c.alpha));
+        assert_eq!(Some(0.0), parse_color("#0000").map(|c| c.alpha));
+        assert_eq!(Some([0, 255, 0, 255]), bytes(parse_color("hsl(120,100%,50%)")));
+        assert_eq!(Some([255, 0, 0, 128]), bytes(parse_color("hsla(360,100%,50%,0.5)")));
+        assert_eq!(Some([102, 51, 153, 255]), bytes(parse_color("rebeccapurple")));
+        assert_eq!(Some(0.0), parse_color("transparent").map(|c| c.alpha));
+        assert_eq!(Some(Rgba::opaque(0.0, 0.0, 0.0)), parse_color("currentColor"));
+        assert_eq!(None, parse_color("bleu"));
+        assert_eq!(None, parse_color("rgb(1,2)"));
+        assert_eq!(None, parse_color("hwb(0,0%,0%)"));
+        assert_eq!(None, parse_color("#12345"));
+    }
+
+    #[test]
+    fn test_is_color() {
+        assert!(is_color("navy"));
+        assert!(is_color("#ff000080"));
+        assert!(is_color("rgba(255,0,0,0.5)"));
+        // parsed, but the spaces and the style sheet characters are not written out
+        assert!(!is_color("rgb(255 0 0)"));
+        assert!(!is_color("red;}svg{display:none"));
+        assert!(!is_color("bleu"));
+    }
+
+    #[test]
+    fn test_over() {
+        let half_red = Rgba {
+            alpha: 0.5,
+            ..Rgba::opaque(1.0, 0.0, 0.0)
+        };
+        assert_eq!(Rgba::opaque(1.0, 0.5, 0.5), half_red.over(&Rgba::opaque(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn test_named_colors_sorted() {
+        assert!(NAMED_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+}
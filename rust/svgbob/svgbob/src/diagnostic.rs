@@ -0,0 +1,86 @@
+use std::fmt;
+
+/// how severe the reported problem is
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq)]
+pub enum Severity {
+    /// the input is rendered but most likely not the way it was intended
+    Warning,
+    /// part of the input could not be rendered at all
+    Error,
+}
+
+/// the cells in the grid a diagnostic refers to,
+/// rows and columns are zero based, `end_column` is exclusive.
+///
+/// The columns are cell columns: the tabs are expanded and the wide characters take up two cells,
+/// so they are not the character columns of the line when there are tabs or wide characters before them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub row: usize,
+    pub start_column: usize,
+    pub end_column: usize,
+}
+
+/// a problem found while laying out the input into the grid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Span {
+    pub fn new(row: usize, start_column: usize, end_column: usize) -> Span {
+        Span {
+            row: row,
+            start_column: start_column,
+            end_column: end_column,
+        }
+    }
+
+    /// a span covering a single cell
+    pub fn cell(row: usize, column: usize) -> Span {
+        Span::new(row, column, column + 1)
+    }
+}
+
+impl Diagnostic {
+    pub fn warning(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            span: span,
+            severity: Severity::Warning,
+            message: message,
+        }
+    }
+
+    pub fn error(span: Span, message: String) -> Diagnostic {
+        Diagnostic {
+            span: span,
+            severity: Severity::Error,
+            message: message,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// displayed as `warning: 3:5: message`, with the 1-based row and cell column
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}:{}: {}",
+            self.severity,
+            self.span.row + 1,
+            self.span.start_column + 1,
+            self.message
+        )
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt::Display;
+use std::str::FromStr;
+use color::is_color;
+use diagnostic::{Diagnostic, Span};
+use grid::MAX_TAB_WIDTH;
+use settings::{Settings, MAX_PRECISION};
+
+/// the start of a directive line,
+/// the directive lines are only read at the top of the input
+const PREFIX: &str = "%% svgbob:";
+
+/// the range of the total scale of the `scale` directives,
+/// beyond it the drawing is either invisible or huge
+const MIN_SCALE: f32 = 0.01;
+const MAX_SCALE: f32 = 100.0;
+
+/// the largest `stroke-width` and `font-size`, before the scale is applied
+const MAX_STROKE_WIDTH: f32 = 100.0;
+const MAX_FONT_SIZE: f32 = 1000.0;
+
+/// apply the directive lines at the top of the input to `settings`
+/// returning the number of lines they take, these lines are not part of the grid.
+///
+/// A directive line is a list of `key=value` separated by spaces:
+///
+/// ```text
+/// %% svgbob: stroke-width=1.5 font-size=12 background=none
+/// ```
+///
+/// The directive lines may be indented, and the blank lines above them are left out with them
+/// as the diagrams embedded in a document usually start with an empty or indented line.
+///
+/// The `scale` directives multiply into one total scale which is applied
+/// after all the other directives, so `stroke-width` and `font-size` are scaled with it
+pub(crate) fn apply_directives(lines: &[&str], settings: &mut Settings, diagnostics: &mut Vec<Diagnostic>) -> usize {
+    let blank = lines.iter().take_while(|line| line.trim().is_empty()).count();
+    let mut count = 0;
+    let mut scale = 1.0;
+    for (y, line) in lines.iter().enumerate().skip(blank) {
+        if !line.trim_start().starts_with(PREFIX) {
+            break;
+        }
+        apply_directive(line, y, settings, &mut scale, diagnostics);
+        count = y + 1;
+    }
+    if scale != 1.0 {
+        settings.scale(scale);
+    }
+    count
+}
+
+/// apply the settings of a directive line, multiplying its `scale` values into `scale`
+fn apply_directive(line: &str, y: usize, settings: &mut Settings, scale: &mut f32, diagnostics: &mut Vec<Diagnostic>) {
+    let indent = line.len() - line.trim_start().len();
+    let mut index = indent + PREFIX.len();
+    for setting in line[index..].split(' ') {
+        let start = settings.str_width(&line[..index]);
+        index += setting.len() + 1;
+        if setting.is_empty() {
+            continue;
+        }
+        let span = Span::new(y, start, start + settings.str_width(setting));
+        let mut key_value = setting.splitn(2, '=');
+        let key = key_value.next().unwrap_or("");
+        let value = match key_value.next() {
+            Some(value) => value,
+            None => {
+                diagnostics.push(Diagnostic::warning(
+                    span,
+                    format!("setting `{}` has no value, expecting `{}=value`", key, key),
+                ));
+                continue;
+            }
+        };
+        // the limit a value out of range is clamped to
+        let mut clamped = None;
+        let applied = match key {
+            "stroke-width" => parse_bounded(value, &mut settings.stroke_width, MAX_STROKE_WIDTH, &mut clamped),
+            "font-size" => parse_bounded(value, &mut settings.font_size, MAX_FONT_SIZE, &mut clamped),
+            "font-family" => is_font_family(value) && parse_into(value, &mut settings.font_family),
+            "stroke" | "stroke-color" => is_color(value) && parse_into(value, &mut settings.stroke_color),
+            "background" | "background-color" => {
+                if value == "none" {
+                    settings.background_color = None;
+                    true
+                } else if is_color(value) {
+                    settings.background_color = Some(value.to_string());
+                    true
+                } else {
+                    false
+                }
+            }
+            "crop" => {
+                if value == "none" {
+                    settings.crop = None;
+                    true
+                } else {
+                    let mut padding = 0.0;
+                    parse_non_negative(value, &mut padding) && {
+                        settings.crop = Some(padding);
+                        true
+                    }
+                }
+            }
+            "tab-width" => parse_clamped(value, &mut settings.tab_width, 0, MAX_TAB_WIDTH, &mut clamped),
+            "cjk-width" => parse_into(value, &mut settings.cjk_width),
+            "source-map" => parse_into(value, &mut settings.source_map),
+            "inline-markers" => parse_into(value, &mut settings.inline_markers),
+            "merge-paths" => parse_into(value, &mut settings.merge_paths),
+            "rect-shapes" => parse_into(value, &mut settings.rect_shapes),
+            "compact" => parse_into(value, &mut settings.compact),
+            "precision" => parse_clamped(value, &mut settings.precision, 0, MAX_PRECISION, &mut clamped),
+            "sizing" => parse_into(value, &mut settings.sizing),
+            "scale" => {
+                let mut factor = 0.0;
+                parse_non_negative(value, &mut factor) && factor > 0.0 && {
+                    *scale = clamp(*scale * factor, MIN_SCALE, MAX_SCALE, &mut clamped);
+                    true
+                }
+            }
+            _ => {
+                diagnostics.push(Diagnostic::warning(
+                    span,
+                    format!("unknown setting `{}` is ignored", key),
+                ));
+                continue;
+            }
+        };
+        if !applied {
+            diagnostics.push(Diagnostic::warning(
+                span,
+                format!("invalid value `{}` for `{}` is ignored", value, key),
+            ));
+        } else if let Some(limit) = clamped {
+            let message = if key == "scale" {
+                format!(
+                    "value `{}` for `scale` puts the total scale out of range, using `{}`",
+                    value, limit
+                )
+            } else {
+                format!("value `{}` for `{}` is out of range, using `{}`", value, key, limit)
+            };
+            diagnostics.push(Diagnostic::warning(span, message));
+        }
+    }
+}
+
+/// parse `value` into `field`, leaving it as is when `value` is invalid
+fn parse_into<T: FromStr>(value: &str, field: &mut T) -> bool {
+    match value.parse() {
+        Ok(parsed) => {
+            *field = parsed;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// parse a finite, non negative number into `field`, leaving it as is otherwise
+fn parse_non_negative(value: &str, field: &mut f32) -> bool {
+    match value.parse::<f32>() {
+        Ok(parsed) if parsed.is_finite() && parsed >= 0.0 => {
+            *field = parsed;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// parse a finite, non negative number into `field` clamped to `0..=max`
+fn parse_bounded(value: &str, field: &mut f32, max: f32, clamped: &mut Option<String>) -> bool {
+    let mut parsed = 0.0;
+    parse_non_negative(value, &mut parsed) && {
+        *field = clamp(parsed, 0.0, max, clamped);
+        true
+    }
+}
+
+/// parse `value` into `field` clamped to `min..=max`
+fn parse_clamped<T>(value: &str, field: &mut T, min: T, max: T, clamped: &mut Option<String>) -> bool
+where
+    T: FromStr + PartialOrd + Display,
+{
+    match value.parse() {
+        Ok(parsed) => {
+            *field = clamp(parsed, min, max, clamped);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// `v` limited to `min..=max`, setting `clamped` to the limit used when it is out of range
+fn clamp<T: PartialOrd + Display>(v: T, min: T, max: T, clamped: &mut Option<String>) -> T {
+    if v < min {
+        *clamped = Some(min.to_string());
+        min
+    } else if v > max {
+        *clamped = Some(max.to_string());
+        max
+    } else {
+        v
+    }
+}
+
+/// a css font family list such as `Menlo,monospace`, the names can not be quoted
+fn is_font_family(value: &str) -> bool {
+    value
+        .split(',')
+        .all(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_directives() {
+        let lines = vec![
+            "%% svgbob: stroke-width=1.5 font-size=12",
+            "%% svgbob:  background=none",
+            "+--->",
+            "%% svgbob: stroke-width=3",
+        ];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        assert_eq!(2, apply_directives(&lines, &mut settings, &mut diagnostics));
+        assert_eq!(1.5, settings.stroke_width);
+        assert_eq!(12.0, settings.font_size);
+        assert_eq!(None, settings.background_color);
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_directives() {
+        let lines = vec!["%% svgbob: stroke-width=thin colour=red font-size"];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        assert_eq!(1, apply_directives(&lines, &mut settings, &mut diagnostics));
+        assert_eq!(2.0, settings.stroke_width);
+        assert_eq!(
+            vec![
+                Diagnostic::warning(
+                    Span::new(0, 11, 28),
+                    "invalid value `thin` for `stroke-width` is ignored".to_string()
+                ),
+                Diagnostic::warning(Span::new(0, 29, 39), "unknown setting `colour` is ignored".to_string()),
+                Diagnostic::warning(
+                    Span::new(0, 40, 49),
+                    "setting `font-size` has no value, expecting `font-size=value`".to_string()
+                ),
+            ],
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_indented_directives() {
+        // the shape of a foremark diagram: `:: %% svgbob: ..` or a diagram starting on the line of its tag
+        let lines = vec!["", " %% svgbob: stroke=red", " %% svgbob: font-size", " +--->"];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        assert_eq!(3, apply_directives(&lines, &mut settings, &mut diagnostics));
+        assert_eq!("red", settings.stroke_color);
+        assert_eq!(
+            vec![Diagnostic::warning(
+                Span::new(2, 12, 21),
+                "setting `font-size` has no value, expecting `font-size=value`".to_string()
+            )],
+            diagnostics
+        );
+
+        // the blank lines stay in the grid when there are no directives below them
+        let lines = vec!["", " +--->"];
+        assert_eq!(0, apply_directives(&lines, &mut settings, &mut diagnostics));
+    }
+
+    #[test]
+    fn test_unsafe_values() {
+        let lines = vec![
+            "%% svgbob: stroke=red}</style><script> background=url(x) font-family=a\"b stroke-color=#f00 \
+             background=rgba(0,0,255,0.5) font-family=Menlo,monospace",
+        ];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        apply_directives(&lines, &mut settings, &mut diagnostics);
+        assert_eq!("#f00", settings.stroke_color);
+        assert_eq!(Some("rgba(0,0,255,0.5)".to_string()), settings.background_color);
+        assert_eq!("Menlo,monospace", settings.font_family);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            vec![
+                "invalid value `red}</style><script>` for `stroke` is ignored",
+                "invalid value `url(x)` for `background` is ignored",
+                "invalid value `a\"b` for `font-family` is ignored",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_unknown_colors() {
+        let lines = vec!["%% svgbob: stroke=navy stroke=bleu background=hwb(0,0%,0%) background=hsl(210,50%,40%)"];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        apply_directives(&lines, &mut settings, &mut diagnostics);
+        assert_eq!("navy", settings.stroke_color);
+        assert_eq!(Some("hsl(210,50%,40%)".to_string()), settings.background_color);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            vec![
+                "invalid value `bleu` for `stroke` is ignored",
+                "invalid value `hwb(0,0%,0%)` for `background` is ignored",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_out_of_range_values() {
+        let lines = vec![
+            "%% svgbob: scale=NaN scale=inf scale=0 stroke-width=-1 font-size=NaN crop=-2",
+            "%% svgbob: tab-width=18446744073709551615 precision=40 scale=1000",
+        ];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        apply_directives(&lines, &mut settings, &mut diagnostics);
+        assert_eq!(MAX_TAB_WIDTH, settings.tab_width);
+        assert_eq!(MAX_PRECISION, settings.precision);
+        assert_eq!(None, settings.crop);
+        assert_eq!(2.0 * MAX_SCALE, settings.stroke_width);
+        assert_eq!(14.0 * MAX_SCALE, settings.font_size);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            vec![
+                "invalid value `NaN` for `scale` is ignored",
+                "invalid value `inf` for `scale` is ignored",
+                "invalid value `0` for `scale` is ignored",
+                "invalid value `-1` for `stroke-width` is ignored",
+                "invalid value `NaN` for `font-size` is ignored",
+                "invalid value `-2` for `crop` is ignored",
+                "value `18446744073709551615` for `tab-width` is out of range, using `32`",
+                "value `40` for `precision` is out of range, using `6`",
+                "value `1000` for `scale` puts the total scale out of range, using `100`",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn test_repeated_scale() {
+        let lines = vec![
+            "%% svgbob: scale=100 scale=100",
+            "%% svgbob: scale=100 font-size=1e30 stroke-width=3",
+        ];
+        let mut settings = Settings::default();
+        let mut diagnostics = vec![];
+        apply_directives(&lines, &mut settings, &mut diagnostics);
+        assert_eq!(8.0 * MAX_SCALE, settings.text_width);
+        assert_eq!(MAX_FONT_SIZE * MAX_SCALE, settings.font_size);
+        assert_eq!(3.0 * MAX_SCALE, settings.stroke_width);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            vec![
+                "value `100` for `scale` puts the total scale out of range, using `100`",
+                "value `100` for `scale` puts the total scale out of range, using `100`",
+                "value `1e30` for `font-size` is out of range, using `1000`",
+            ],
+            messages
+        );
+
+        // the scales below the limit multiply into the total
+        let lines = vec!["%% svgbob: scale=2", "%% svgbob: scale=0.25"];
+        let mut settings = Settings::default();
+        apply_directives(&lines, &mut settings, &mut diagnostics);
+        assert_eq!(4.0, settings.text_width);
+    }
+}
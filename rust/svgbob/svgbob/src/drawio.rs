@@ -0,0 +1,238 @@
+use std::fmt::Write;
+use element::{num, width_xml_text, ArcSegment, Element, Feature, Stroke};
+use graph::{connectors, features, find_rects, get_nodes, node_around, node_at, touches};
+use grid::{svg_escape, Grid};
+use point::Point;
+
+impl Grid {
+    /// get the diagram as a draw.io / diagrams.net file of mxGraph XML.
+    ///
+    /// The closed rectangles become boxes labeled with the text inside them,
+    /// the lines and arcs become edges attached to the boxes they end at,
+    /// the circles become ellipses and the rest of the text becomes text labels.
+    /// The coordinates are those of the svg, a cell is `text_width` by `text_height`,
+    /// the ones which are NaN or infinite are left out and draw.io uses its defaults
+    pub fn get_drawio(&self) -> String {
+        let settings = &self.settings;
+        let elements: Vec<Element> = self.get_elements().into_iter().flat_map(|group| group).collect();
+        let rects = find_rects(&elements);
+        let nodes = get_nodes(&rects, &elements, settings);
+        let connectors = connectors(&elements, &rects);
+        let font = format!(
+            "fontFamily={};{}",
+            svg_escape(&settings.font_family),
+            style_value("fontSize", settings.font_size)
+        );
+
+        let mut cells = String::new();
+        for node in &nodes {
+            let rounded = rects[node.id].radii.iter().fold(0.0, |max: f32, &r| max.max(r));
+            let style = if node.rounded {
+                format!("rounded=1;absoluteArcSize=1;{}", style_value("arcSize", rounded * 2.0))
+            } else {
+                "rounded=0;".to_string()
+            };
+            vertex(
+                &mut cells,
+                &format!("n{}", node.id),
+                &svg_escape(&node.label).replace('\n', "&#xa;"),
+                &format!("{}whiteSpace=wrap;html=0;{}", style, font),
+                (node.x, node.y, node.width, node.height),
+            );
+        }
+        for (i, elm) in elements.iter().enumerate() {
+            match *elm {
+                // the text inside the boxes is in their labels
+                Element::Text(ref loc, ref text, _) if node_around(&nodes, elm, settings).is_none() => {
+                    vertex(
+                        &mut cells,
+                        &format!("t{}", i),
+                        text,
+                        &format!("text;html=0;align=left;verticalAlign=middle;spacing=0;{}", font),
+                        (
+                            loc.x as f32 * settings.text_width,
+                            loc.y as f32 * settings.text_height,
+                            width_xml_text(text, settings) as f32 * settings.text_width,
+                            settings.text_height,
+                        ),
+                    );
+                }
+                Element::Circle(ref center, r, _) => {
+                    vertex(
+                        &mut cells,
+                        &format!("c{}", i),
+                        "",
+                        "ellipse;aspect=fixed;html=0;",
+                        (center.x - r, center.y - r, r * 2.0, r * 2.0),
+                    );
+                }
+                _ => (),
+            }
+        }
+        for (i, elm) in connectors.iter().enumerate() {
+            let (s, e) = elm.endpoints().expect("connectors are lines and arcs");
+            let (start_feature, end_feature) = features(elm);
+            let (start_arrow, start_fill) = arrow(start_feature);
+            let (end_arrow, end_fill) = arrow(end_feature);
+            let mut style = format!(
+                "html=1;rounded=0;startArrow={};startFill={};endArrow={};endFill={};",
+                start_arrow, start_fill, end_arrow, end_fill
+            );
+            let mut waypoint = None;
+            match *elm {
+                Element::Line(_, _, Stroke::Dashed, ..) => style.push_str("dashed=1;"),
+                Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, ref stroke, ..) => {
+                    style.push_str("curved=1;");
+                    if *stroke == Stroke::Dashed {
+                        style.push_str("dashed=1;");
+                    }
+                    let arc = ArcSegment {
+                        start: s.clone(),
+                        end: e.clone(),
+                        radius: radius,
+                        arc_flag: arc_flag.clone(),
+                        sweep: sweep,
+                    }
+                    .geometry();
+                    // the curved edge is drawn through the point halfway along the arc
+                    waypoint = Some(arc.point_at(arc.start_angle + arc.sweep_angle / 2.0));
+                }
+                _ => (),
+            }
+            // the ends which are not joined to the other connectors are attached to the nearest box
+            let attach = |p: &Point, end: &str| {
+                if connectors.iter().enumerate().any(|(j, other)| j != i && touches(other, p)) {
+                    return String::new();
+                }
+                match node_at(&rects, p, settings) {
+                    Some(id) => format!(" {}=\"n{}\"", end, id),
+                    None => String::new(),
+                }
+            };
+            let source = attach(s, "source");
+            let target = attach(e, "target");
+            writeln!(
+                cells,
+                "        <mxCell id=\"e{}\" style=\"{}\" edge=\"1\" parent=\"1\"{}{}>",
+                i, style, source, target
+            ).expect("writing to a string");
+            writeln!(cells, "          <mxGeometry relative=\"1\" as=\"geometry\">").expect("writing to a string");
+            writeln!(cells, "            <mxPoint{} as=\"sourcePoint\"/>", point(s)).expect("writing to a string");
+            writeln!(cells, "            <mxPoint{} as=\"targetPoint\"/>", point(e)).expect("writing to a string");
+            if let Some(p) = waypoint {
+                writeln!(cells, "            <Array as=\"points\"><mxPoint{}/></Array>", point(&p))
+                    .expect("writing to a string");
+            }
+            writeln!(cells, "          </mxGeometry>\n        </mxCell>").expect("writing to a string");
+        }
+
+        let (width, height) = self.get_size();
+        let mut drawio = String::new();
+        writeln!(drawio, "<mxfile host=\"svgbob\">").expect("writing to a string");
+        writeln!(drawio, "  <diagram id=\"svgbob\" name=\"Page-1\">").expect("writing to a string");
+        writeln!(
+            drawio,
+            "    <mxGraphModel grid=\"1\"{} page=\"1\"{}{}>",
+            attribute("gridSize", settings.text_width),
+            attribute("pageWidth", width),
+            attribute("pageHeight", height)
+        ).expect("writing to a string");
+        drawio.push_str("      <root>\n        <mxCell id=\"0\"/>\n        <mxCell id=\"1\" parent=\"0\"/>\n");
+        drawio.push_str(&cells);
+        drawio.push_str("      </root>\n    </mxGraphModel>\n  </diagram>\n</mxfile>\n");
+        drawio
+    }
+}
+
+/// write a vertex cell with its geometry `x, y, width, height`,
+/// `value` is already escaped
+fn vertex(cells: &mut String, id: &str, value: &str, style: &str, (x, y, width, height): (f32, f32, f32, f32)) {
+    writeln!(
+        cells,
+        "        <mxCell id=\"{}\" value=\"{}\" style=\"{}\" vertex=\"1\" parent=\"1\">\n          \
+         <mxGeometry{}{}{}{} as=\"geometry\"/>\n        </mxCell>",
+        id,
+        value,
+        style,
+        attribute("x", x),
+        attribute("y", y),
+        attribute("width", width),
+        attribute("height", height)
+    ).expect("writing to a string");
+}
+
+/// the `x` and `y` attributes of an `mxPoint` at `p`
+fn point(p: &Point) -> String {
+    format!("{}{}", attribute("x", p.x), attribute("y", p.y))
+}
+
+/// the attribute ` name="v"`, left out when `v` is NaN or infinite so draw.io uses its default
+fn attribute(name: &str, v: f32) -> String {
+    if v.is_finite() {
+        format!(" {}=\"{}\"", name, num(v))
+    } else {
+        String::new()
+    }
+}
+
+/// the style `name=v;`, left out when `v` is NaN or infinite so draw.io uses its default
+fn style_value(name: &str, v: f32) -> String {
+    if v.is_finite() {
+        format!("{}={};", name, num(v))
+    } else {
+        String::new()
+    }
+}
+
+/// the draw.io arrow drawn for the feature and whether it is filled
+fn arrow(feature: &Feature) -> (&'static str, u8) {
+    match *feature {
+        Feature::Arrow => ("block", 1),
+        Feature::ClearArrow => ("block", 0),
+        Feature::Circle => ("oval", 1),
+        Feature::Square => ("box", 1),
+        Feature::OpenCircle | Feature::BigOpenCircle => ("oval", 0),
+        Feature::Nothing => ("none", 0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use settings::Settings;
+
+    #[test]
+    fn test_drawio() {
+        let diagram = "+-----+\n| A&B |--> done\n+-----+";
+        let drawio = Grid::from_str(diagram, &Settings::default()).get_drawio();
+        assert!(drawio.starts_with("<mxfile host=\"svgbob\">\n  <diagram id=\"svgbob\" name=\"Page-1\">\n"));
+        assert!(drawio.contains(
+            "<mxCell id=\"n0\" value=\"A&amp;B\" style=\"rounded=0;whiteSpace=wrap;html=0;"
+        ));
+        assert!(drawio.contains("<mxGeometry x=\"4\" y=\"8\" width=\"48\" height=\"32\" as=\"geometry\"/>"));
+        assert!(drawio.contains(
+            "style=\"html=1;rounded=0;startArrow=none;startFill=0;endArrow=block;endFill=1;\" \
+             edge=\"1\" parent=\"1\" source=\"n0\">"
+        ));
+        assert!(drawio.contains("value=\"done\" style=\"text;"));
+        assert!(drawio.ends_with("</root>\n    </mxGraphModel>\n  </diagram>\n</mxfile>\n"));
+    }
+
+    #[test]
+    fn test_drawio_not_finite() {
+        let mut settings = Settings::default();
+        let diagram = "+---+\n| A |--> b\n+---+  o";
+        let drawio = Grid::from_str(diagram, &settings).get_drawio();
+        assert!(drawio.contains("<mxGraphModel grid=\"1\" gridSize=\"8\" page=\"1\""));
+        // the geometry which is not finite is left out
+        settings.text_width = std::f32::NAN;
+        settings.font_size = std::f32::INFINITY;
+        let drawio = Grid::from_str(diagram, &settings).get_drawio();
+        assert!(drawio.contains("<mxGraphModel grid=\"1\" page=\"1\""));
+        assert!(!drawio.contains("gridSize"));
+        assert!(drawio.contains("<mxGeometry y=\"16\" height=\"16\" as=\"geometry\"/>"));
+        assert!(drawio.contains("<mxPoint as=\"sourcePoint\"/>"));
+        assert!(!drawio.contains("NaN"));
+        assert!(!drawio.contains("inf"));
+    }
+}
@@ -1,18 +1,21 @@
 use std::cmp::Ordering;
+use std::f32::consts::PI;
 use svg_element::SvgElement;
 use svg::Node;
 use svg::node::element::{
     Circle as SvgCircle,
     Line as SvgLine,
     Path as SvgPath,
+    Polygon as SvgPolygon,
     Text as SvgText,
 };
 use grid::svg_escape;
 use point::collinear;
-use settings::Settings;
+use settings::{Settings, MAX_PRECISION};
 
 use point::Point;
 use loc::Loc;
+use renderer::MarkerEnd;
 use element::{
     Stroke::{Solid,Dashed},
     ArcFlag::{Minor,Major},
@@ -21,24 +24,106 @@ use element::{
         Square,OpenCircle,
         BigOpenCircle,Nothing},
 };
-use unicode_width::UnicodeWidthStr;
 //use point;
 use svg;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 
 
-#[derive(Debug, Clone, PartialEq, PartialOrd )]
+/// The elements are compared without their cells,
+/// the same stroke made from different cells is still the same stroke.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Element {
-    Circle(Point, f32),
+    Circle(Point, f32, Cells),
     //   start,  end,  stroke , start_feature, end feature
-    Line(Point, Point, Stroke, Feature, Feature),
+    Line(Point, Point, Stroke, Feature, Feature, Cells),
     //   start, end, radius,   sweep,   stroke, start_feat, end_feat
-    Arc(Point, Point, f32, ArcFlag, bool, Stroke, Feature, Feature),
-    Text(Loc, String),
+    Arc(Point, Point, f32, ArcFlag, bool, Stroke, Feature, Feature, Cells),
+    Text(Loc, #[cfg_attr(feature = "serde", serde(with = "xml_text"))] String, Cells),
+}
+
+/// the text of `Element::Text` is kept escaped for svg,
+/// it is serialized as it is in the diagram
+#[cfg(feature = "serde")]
+mod xml_text {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use grid::{svg_escape, xml_unescape};
+
+    pub fn serialize<S: Serializer>(text: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&xml_unescape(text))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        String::deserialize(deserializer).map(|text| svg_escape(&text))
+    }
+}
+
+/// the cells of the grid an element is made from, the corners are inclusive.
+/// Only recorded when `Settings::source_map` is set.
+///
+/// The cells take no part in comparing elements, see `Element`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Cells(Option<(Loc, Loc)>);
+
+impl Cells {
+    pub fn none() -> Cells {
+        Cells(None)
+    }
+
+    pub fn new(start: &Loc, end: &Loc) -> Cells {
+        Cells(Some((start.clone(), end.clone())))
+    }
+
+    /// the cells of a single location
+    pub fn at(loc: &Loc) -> Cells {
+        Cells::new(loc, loc)
+    }
+
+    /// the top left and bottom right cell, when recorded
+    pub fn corners(&self) -> Option<(&Loc, &Loc)> {
+        self.0.as_ref().map(|&(ref s, ref e)| (s, e))
+    }
+
+    /// the smallest span of cells covering both
+    pub fn merge(&self, other: &Cells) -> Cells {
+        match (&self.0, &other.0) {
+            (&Some((ref s1, ref e1)), &Some((ref s2, ref e2))) => Cells::new(
+                &Loc::new(s1.x.min(s2.x), s1.y.min(s2.y)),
+                &Loc::new(e1.x.max(e2.x), e1.y.max(e2.y)),
+            ),
+            (&Some(_), &None) => self.clone(),
+            _ => other.clone(),
+        }
+    }
+
+    fn shift_rows(&self, rows: i32) -> Cells {
+        match self.0 {
+            Some((ref s, ref e)) => Cells::new(&Loc::new(s.x, s.y + rows), &Loc::new(e.x, e.y + rows)),
+            None => Cells::none(),
+        }
+    }
+
+    /// the value of the `data-cells` attribute: `x0,y0,x1,y1`
+    pub(crate) fn to_attribute(&self) -> Option<String> {
+        self.0.as_ref().map(|&(ref s, ref e)| format!("{},{},{},{}", s.x, s.y, e.x, e.y))
+    }
+}
+
+/// the fields of an element but its cells, the elements are compared by them
+#[derive(PartialEq, PartialOrd)]
+enum ElementKey<'a> {
+    Circle(&'a Point, f32),
+    Line(&'a Point, &'a Point, &'a Stroke, &'a Feature, &'a Feature),
+    Arc(&'a Point, &'a Point, f32, &'a ArcFlag, bool, &'a Stroke, &'a Feature, &'a Feature),
+    Text(&'a Loc, &'a str),
 }
 
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Stroke {
     Solid,
     Dashed,
@@ -46,6 +131,7 @@ pub enum Stroke {
 
 //TODO: rename to marker
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Feature {
     Arrow,  //end
     ClearArrow,
@@ -58,22 +144,215 @@ pub enum Feature {
 
 impl Feature {
 
-    fn get_marker(&self) -> Option<&str> {
+    /// the reference to the marker of this feature,
+    /// there are no markers when the features are drawn as their `shape`
+    pub(crate) fn get_marker(&self, settings: &Settings) -> Option<String> {
+        if settings.inline_markers {
+            return None;
+        }
+        let id = match *self {
+            Nothing =>  return None,
+            Arrow => "triangle",
+            ClearArrow => "clear_triangle",
+            Circle => "circle",
+            Square => "square",
+            OpenCircle => "open_circle",
+            BigOpenCircle => "big_open_circle",
+        };
+        Some(format!("url(#{})", settings.id(id)))
+    }
+
+    /// how far the marker reaches from the end of the line
+    fn extent(&self, settings: &Settings) -> f32 {
+        let sw = settings.stroke_width;
+        match *self {
+            Nothing => 0.0,
+            Arrow => 5.0 * sw,
+            ClearArrow => 9.0 * sw,
+            Circle => 2.0 * sw,
+            Square => 3.6 * sw,
+            OpenCircle => 2.5 * sw,
+            BigOpenCircle => 3.5 * sw,
+        }
+    }
+
+    /// the polygon or circle drawn in place of the marker at `at`,
+    /// `direction` is the unit vector of the stroke at that point.
+    /// The shapes have the same size as the markers at this stroke width
+    pub(crate) fn shape(&self, at: &Point, direction: (f32, f32), settings: &Settings) -> Option<Shape> {
+        let sw = settings.stroke_width;
+        let (ux, uy) = direction;
+        // `along` the stroke and `across` to the left of it
+        let point = |along: f32, across: f32| {
+            Point::new(at.x + ux * along + uy * across, at.y + uy * along - ux * across)
+        };
+        let shape = match *self {
+            Nothing => return None,
+            Arrow => Shape::Polygon(
+                vec![point(-4.0 * sw, 3.0 * sw), point(4.0 * sw, 0.0), point(-4.0 * sw, -3.0 * sw)],
+                false,
+            ),
+            ClearArrow => Shape::Polygon(
+                vec![point(0.5 * sw, 2.5 * sw), point(8.5 * sw, 0.0), point(0.5 * sw, -2.5 * sw)],
+                true,
+            ),
+            Circle => Shape::Circle(at.clone(), 2.0 * sw, false),
+            Square => Shape::Polygon(
+                vec![
+                    point(-2.5 * sw, 2.5 * sw),
+                    point(2.5 * sw, 2.5 * sw),
+                    point(2.5 * sw, -2.5 * sw),
+                    point(-2.5 * sw, -2.5 * sw),
+                ],
+                false,
+            ),
+            OpenCircle => Shape::Circle(at.clone(), 2.0 * sw, true),
+            BigOpenCircle => Shape::Circle(at.clone(), 3.0 * sw, true),
+        };
+        Some(shape)
+    }
+}
+
+/// the shape of a marker, open shapes are filled with the background and outlined
+/// while the others are filled with the stroke color
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Shape {
+    Polygon(Vec<Point>, bool),
+    Circle(Point, f32, bool),
+}
+
+impl Shape {
+    pub(crate) fn to_svg(&self, settings: &Settings) -> SvgElement {
+        let class = |open: bool| if open { "bg_fill fg_stroke" } else { "fg_fill" };
         match *self {
-            Nothing =>  None,
-            Arrow => Some("url(#triangle)"),
-            ClearArrow => Some("url(#clear_triangle)"),
-            Circle => Some("url(#circle)"),
-            Square => Some("url(#square)"),
-            OpenCircle => Some("url(#open_circle)"),
-            BigOpenCircle => Some("url(#big_open_circle)"),
+            Shape::Polygon(ref points, open) => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|p| format!("{},{}", settings.round(p.x), settings.round(p.y)))
+                    .collect();
+                SvgElement::Polygon(SvgPolygon::new()
+                    .set("class", class(open))
+                    .set("points", points.join(" ")))
+            }
+            Shape::Circle(ref c, r, open) => {
+                SvgElement::Circle(SvgCircle::new()
+                    .set("class", class(open))
+                    .set("cx", settings.round(c.x))
+                    .set("cy", settings.round(c.y))
+                    .set("r", settings.round(r)))
+            }
         }
     }
 }
 
+/// the unit vector from `a` to `b`
+fn direction(a: &Point, b: &Point) -> (f32, f32) {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = (dx * dx + dy * dy).sqrt();
+    if length == 0.0 {
+        (1.0, 0.0)
+    } else {
+        (dx / length, dy / length)
+    }
+}
+
+/// the center of the svg arc from `s` to `e`,
+/// following the endpoint to center conversion of the svg specification
+pub(crate) fn arc_center(s: &Point, e: &Point, radius: f32, large_arc: bool, sweep: bool) -> Point {
+    let (hx, hy) = ((s.x - e.x) / 2.0, (s.y - e.y) / 2.0);
+    let half = hx * hx + hy * hy;
+    if half == 0.0 {
+        return s.clone();
+    }
+    // radii too small to reach are scaled up, putting the center halfway
+    let mut coef = ((radius * radius - half) / half).max(0.0).sqrt();
+    if large_arc == sweep {
+        coef = -coef;
+    }
+    Point::new(coef * hy + (s.x + e.x) / 2.0, -coef * hx + (s.y + e.y) / 2.0)
+}
+
+/// `v` rounded to `decimals` decimal places, at most `MAX_PRECISION`,
+/// adding zero turns `-0` into `0`
+pub(crate) fn round_to(v: f32, decimals: i32) -> f32 {
+    let scale = 10f32.powi(decimals.min(MAX_PRECISION as i32));
+    if !scale.is_finite() || scale == 0.0 {
+        return v;
+    }
+    (v * scale).round() / scale + 0.0
+}
 
+/// the numbers of the exported documents are written with 2 decimals at most
+pub(crate) fn num(v: f32) -> f32 {
+    round_to(v, 2)
+}
+
+/// the direction of the arc around `center` at point `p`,
+/// a sweep goes in the direction of increasing angles, clockwise on the screen
+fn arc_direction(center: &Point, p: &Point, sweep: bool) -> (f32, f32) {
+    let (dx, dy) = direction(center, p);
+    if sweep {
+        (-dy, dx)
+    } else {
+        (dy, -dx)
+    }
+}
+
+
+
+/// an arc of the svg path command `A` from `start` to `end`,
+/// a sweep goes in the direction of increasing angles, clockwise on the screen
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ArcSegment {
+    pub start: Point,
+    pub end: Point,
+    pub radius: f32,
+    pub arc_flag: ArcFlag,
+    pub sweep: bool,
+}
+
+impl ArcSegment {
+    /// the circle the arc is drawn on and the angles it goes through
+    pub(crate) fn geometry(&self) -> ArcGeometry {
+        let center = arc_center(&self.start, &self.end, self.radius, self.arc_flag == Major, self.sweep);
+        let angle = |p: &Point| (p.y - center.y).atan2(p.x - center.x);
+        let (a0, a1) = (angle(&self.start), angle(&self.end));
+        // a sweep goes in the direction of increasing angles
+        let sweep_angle = if self.sweep {
+            (a1 - a0).rem_euclid(2.0 * PI)
+        } else {
+            -(a0 - a1).rem_euclid(2.0 * PI)
+        };
+        let radius = ((self.start.x - center.x).powi(2) + (self.start.y - center.y).powi(2)).sqrt();
+        ArcGeometry {
+            center: center,
+            radius: radius,
+            start_angle: a0,
+            sweep_angle: sweep_angle,
+        }
+    }
+}
+
+/// the circle an arc is drawn on, the angles are in radians
+pub(crate) struct ArcGeometry {
+    pub(crate) center: Point,
+    /// the radius of the arc, scaled up when it is too small to reach the end
+    pub(crate) radius: f32,
+    pub(crate) start_angle: f32,
+    /// the angle from the start to the end, negative when it is not a sweep
+    pub(crate) sweep_angle: f32,
+}
+
+impl ArcGeometry {
+    /// the point of the circle at `angle`
+    pub(crate) fn point_at(&self, angle: f32) -> Point {
+        Point::new(self.center.x + self.radius * angle.cos(), self.center.y + self.radius * angle.sin())
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ArcFlag {
     Major,
     Minor,
@@ -91,120 +370,124 @@ impl Eq for Element{
 }
 
 pub fn line(a: &Point, b: &Point) -> Element {
-    Element::Line(a.clone(), b.clone(), Solid, Nothing, Nothing)
+    Element::Line(a.clone(), b.clone(), Solid, Nothing, Nothing, Cells::none())
 }
 
 pub fn dashed_line(a: &Point, b: &Point) -> Element {
-    Element::Line(a.clone(), b.clone(), Dashed, Nothing, Nothing)
+    Element::Line(a.clone(), b.clone(), Dashed, Nothing, Nothing, Cells::none())
 }
 
 pub fn circle_start_line(a: &Point, b: &Point) -> Element {
-    Element::Line(a.clone(), b.clone(), Solid, Circle, Nothing)
+    Element::Line(a.clone(), b.clone(), Solid, Circle, Nothing, Cells::none())
 }
 pub fn square_start_line(a: &Point, b: &Point) -> Element {
-    Element::Line(a.clone(), b.clone(), Solid, Square, Nothing)
+    Element::Line(a.clone(), b.clone(), Solid, Square, Nothing, Cells::none())
 }
 
 pub fn circle_open_line(a: &Point, b: &Point) -> Element {
-    Element::Line(a.clone(), b.clone(), Solid, OpenCircle, Nothing)
+    Element::Line(a.clone(), b.clone(), Solid, OpenCircle, Nothing, Cells::none())
 }
 
 pub fn big_circle_open_line(a: &Point, b: &Point) -> Element {
-    Element::Line(a.clone(), b.clone(), Solid, BigOpenCircle, Nothing)
+    Element::Line(a.clone(), b.clone(), Solid, BigOpenCircle, Nothing, Cells::none())
 }
 
 
 pub fn arc(a: &Point, b: &Point, r: f32) -> Element {
-    Element::Arc(a.clone(), b.clone(), r, Minor, false, Solid, Nothing, Nothing)
+    Element::Arc(a.clone(), b.clone(), r, Minor, false, Solid, Nothing, Nothing, Cells::none())
 }
 
 
 pub fn open_circle(c: &Point, r: f32) -> Element {
-    Element::Circle(c.clone(), r.clone())
+    Element::Circle(c.clone(), r.clone(), Cells::none())
 }
 
 pub fn arrow_line(s: &Point, e: &Point) -> Element {
-    Element::Line(s.clone(), e.clone(), Solid, Nothing, Arrow)
+    Element::Line(s.clone(), e.clone(), Solid, Nothing, Arrow, Cells::none())
 }
 
 pub fn clear_arrow_line(s: &Point, e: &Point) -> Element {
-    Element::Line(s.clone(), e.clone(), Solid, Nothing, ClearArrow)
+    Element::Line(s.clone(), e.clone(), Solid, Nothing, ClearArrow, Cells::none())
 }
 
 pub fn start_arrow_line(s: &Point, e: &Point) -> Element {
-    Element::Line(s.clone(), e.clone(), Solid, Arrow, Nothing)
+    Element::Line(s.clone(), e.clone(), Solid, Arrow, Nothing, Cells::none())
 }
 
 pub fn text(loc: &Loc, txt: &str) -> Element {
-    Element::Text(loc.clone(), svg_escape(txt))
+    Element::Text(loc.clone(), svg_escape(txt), Cells::none())
 }
 
 /// Get the EAW width of an XML-escaped string.
 ///
 /// This function only supports entities generated by `svg_escape`.
-fn width_xml_text(s: &str) -> usize {
+pub(crate) fn width_xml_text(s: &str, settings: &Settings) -> usize {
     let mut i = 0;
     let mut width = 0;
     while let Some(k) = s[i..].find('&') {
-        width += s[i..i + k].width();
+        width += settings.str_width(&s[i..i + k]);
         width += 1; // Characters escaped by `svg_escape` are always single-cell wide
         i += k;
 
         // Skip to the corresponding `;`
         i += s[i..].find(';').unwrap_or(s.len() - i - 1) + 1;
     }
-    width += s[i..].width();
+    width += settings.str_width(&s[i..]);
     width
 }
 
-impl Element {
+impl PartialEq for Element {
+    fn eq(&self, other: &Element) -> bool {
+        self.key() == other.key()
+    }
+}
 
-    pub fn shares_endpoints(&self, other: &Element) -> bool {
-        match *self{
-            Element::Line(ref s, ref e, _, _,_) => {
-                match *other{
-                    Element::Line(ref s2, ref e2, _, _,_) => {
-                        s == s2 || e == e2 || s == e2 || s2 == e
-                    }
-                    Element::Arc(ref s2, ref e2, _,_,_,_,_,_) => {
-                        s == s2 || e == e2 || s == e2 || s2 == e
-                    }
-                    _ => false
-                }
-            }
-            Element::Arc(ref s, ref e, _,_,_,_,_,_) => {
-                match *other{
-                    Element::Line(ref s2, ref e2, _, _,_) => {
-                        s == s2 || e == e2 || s == e2 || s2 == e
-                    }
-                    Element::Arc(ref s2, ref e2, _,_,_,_,_,_) => {
-                        s == s2 || e == e2 || s == e2 || s2 == e
-                    }
-                    _ => false
-                }
+impl PartialOrd for Element {
+    fn partial_cmp(&self, other: &Element) -> Option<Ordering> {
+        self.key().partial_cmp(&other.key())
+    }
+}
+
+impl Element {
+    fn key(&self) -> ElementKey<'_> {
+        match *self {
+            Element::Circle(ref c, r, _) => ElementKey::Circle(c, r),
+            Element::Line(ref s, ref e, ref stroke, ref sf, ref ef, _) => ElementKey::Line(s, e, stroke, sf, ef),
+            Element::Arc(ref s, ref e, r, ref arc_flag, sweep, ref stroke, ref sf, ref ef, _) => {
+                ElementKey::Arc(s, e, r, arc_flag, sweep, stroke, sf, ef)
             }
-            _ => false
+            Element::Text(ref loc, ref text, _) => ElementKey::Text(loc, text),
         }
     }
+
+    /// the start and end point of lines and arcs
+    pub fn endpoints(&self) -> Option<(&Point, &Point)> {
+        match *self {
+            Element::Line(ref s, ref e, _, _, _, _) => Some((s, e)),
+            Element::Arc(ref s, ref e, _, _, _, _, _, _, _) => Some((s, e)),
+            _ => None,
+        }
+    }
+
     // if this element can reduce the other, return the new reduced element
     // for line it has to be collinear and in can connect start->end->start
     // for text, the other text should apear on the right side of this text
-    pub fn reduce(&self, other: &Element) -> Option<Element> {
+    pub fn reduce(&self, other: &Element, settings: &Settings) -> Option<Element> {
         // if same then return one
         if self == other{
-            return Some(other.clone())
+            return Some(other.merge_cells(self.cells()))
         }
         match *self {
-            Element::Line(ref s, ref e, ref stroke, ref start_feature, ref end_feature) => {
+            Element::Line(ref s, ref e, ref stroke, ref start_feature, ref end_feature, ref cells) => {
                 match *other {
-                    Element::Line(ref s2, ref e2, ref stroke2, ref start_feature2, ref end_feature2) => {
+                    Element::Line(ref s2, ref e2, ref stroke2, ref start_feature2, ref end_feature2, ref cells2) => {
                         // note: dual 3 point check for trully collinear lines
                         if collinear(s, e, s2)
                             && collinear(s, e, e2)
                             && stroke == stroke2{
                             // same length line
                             if s == s2 && e == e2 && start_feature == start_feature2 && end_feature == end_feature2 {
-                                return Some(other.clone())
+                                return Some(other.merge_cells(cells))
                             }
 
                             // extend 1 with 2
@@ -222,7 +505,8 @@ impl Element {
                                                 e2.clone(),
                                                 stroke.clone(),
                                                 start_feature.clone(),
-                                                end_feature2.clone()
+                                                end_feature2.clone(),
+                                                cells.merge(cells2),
                                                 ));
                                 }
                             }
@@ -240,6 +524,7 @@ impl Element {
                                             stroke.clone(),
                                             start_feature.clone(),
                                             start_feature2.clone(),
+                                            cells.merge(cells2),
                                             ));
                                 }
                             }
@@ -258,6 +543,7 @@ impl Element {
                                             stroke.clone(),
                                             end_feature.clone(),
                                             end_feature2.clone(),
+                                            cells.merge(cells2),
                                             ));
                                }
                             }
@@ -278,6 +564,7 @@ impl Element {
                                             stroke.clone(),
                                             start_feature2.clone(),
                                             end_feature.clone(),
+                                            cells.merge(cells2),
                                             ));
                                 }
                             }
@@ -288,14 +575,14 @@ impl Element {
                     _ => None,
                 }
             }
-            Element::Text(ref loc, ref text) => {
+            Element::Text(ref loc, ref text, ref cells) => {
                 match *other {
-                    Element::Text(ref loc2, ref text2) => {
+                    Element::Text(ref loc2, ref text2, ref cells2) => {
                         // reduce if other is next to it
-                        let uwidth = text.width() as i32;
+                        let uwidth = width_xml_text(text, settings) as i32;
                         if loc.y == loc2.y && loc.x + uwidth == loc2.x {
                             let merged_text = text.clone() + text2;
-                            let reduced = Some(Element::Text(loc.clone(), merged_text));
+                            let reduced = Some(Element::Text(loc.clone(), merged_text, cells.merge(cells2)));
                             reduced
                         } else {
                             None
@@ -309,41 +596,211 @@ impl Element {
     }
 
 
-    /// convert drawing element to SVG element
+    /// move this element down by `rows` rows of the grid, up if negative
+    pub fn shift_rows(&self, rows: i32, settings: &Settings) -> Element {
+        let dy = rows as f32 * settings.text_height;
+        let shift = |p: &Point| Point::new(p.x, p.y + dy);
+        match *self {
+            Element::Circle(ref c, r, ref cells) => Element::Circle(shift(c), r, cells.shift_rows(rows)),
+            Element::Line(ref s, ref e, ref stroke, ref start_feature, ref end_feature, ref cells) => {
+                Element::Line(shift(s), shift(e), stroke.clone(), start_feature.clone(), end_feature.clone(),
+                    cells.shift_rows(rows))
+            }
+            Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, ref stroke, ref start_feature, ref end_feature, ref cells) => {
+                Element::Arc(shift(s), shift(e), radius, arc_flag.clone(), sweep,
+                    stroke.clone(), start_feature.clone(), end_feature.clone(), cells.shift_rows(rows))
+            }
+            Element::Text(ref loc, ref string, ref cells) => {
+                Element::Text(Loc::new(loc.x, loc.y + rows), string.clone(), cells.shift_rows(rows))
+            }
+        }
+    }
+
+    /// the top left and bottom right corner of the area this element is drawn in
+    pub fn bounds(&self, settings: &Settings) -> (Point, Point) {
+        let half = settings.stroke_width / 2.0;
+        let around = |p: &Point, r: f32| (Point::new(p.x - r, p.y - r), Point::new(p.x + r, p.y + r));
+        let union = |(min1, max1): (Point, Point), (min2, max2): (Point, Point)| {
+            (
+                Point::new(min1.x.min(min2.x), min1.y.min(min2.y)),
+                Point::new(max1.x.max(max2.x), max1.y.max(max2.y)),
+            )
+        };
+        match *self {
+            Element::Circle(ref c, r, _) => around(c, r + half),
+            Element::Line(ref s, ref e, _, ref start_feature, ref end_feature, _) => union(
+                around(s, start_feature.extent(settings).max(half)),
+                around(e, end_feature.extent(settings).max(half)),
+            ),
+            Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, _, ref start_feature, ref end_feature, _) => {
+                let mut bounds = union(
+                    around(s, start_feature.extent(settings).max(half)),
+                    around(e, end_feature.extent(settings).max(half)),
+                );
+                // the leftmost, topmost, rightmost and bottommost points of the circle
+                // are on the arc when the arc passes their angle
+                let center = arc_center(s, e, radius, *arc_flag == Major, sweep);
+                let r = ((s.x - center.x).powi(2) + (s.y - center.y).powi(2)).sqrt();
+                let angle = |p: &Point| (p.y - center.y).atan2(p.x - center.x);
+                let (start, end) = if sweep { (angle(s), angle(e)) } else { (angle(e), angle(s)) };
+                let tau = 2.0 * PI;
+                let span = (end - start).rem_euclid(tau);
+                for quarter in 0..4 {
+                    let extreme = quarter as f32 * tau / 4.0;
+                    if (extreme - start).rem_euclid(tau) <= span {
+                        let p = Point::new(center.x + r * extreme.cos(), center.y + r * extreme.sin());
+                        bounds = union(bounds, around(&p, half));
+                    }
+                }
+                bounds
+            }
+            Element::Text(ref loc, ref text, _) => {
+                let width = width_xml_text(text, settings) as f32;
+                (
+                    Point::new(loc.x as f32 * settings.text_width, loc.y as f32 * settings.text_height),
+                    Point::new(
+                        (loc.x as f32 + width) * settings.text_width,
+                        (loc.y + 1) as f32 * settings.text_height,
+                    ),
+                )
+            }
+        }
+    }
+
+    /// the shapes drawn in place of the markers when `Settings::inline_markers` is set,
+    /// oriented the same way as markers with `orient="auto"`
+    pub fn get_feature_shapes(&self, settings: &Settings) -> Vec<SvgElement> {
+        self.get_marker_shapes(settings).iter().map(|shape| shape.to_svg(settings)).collect()
+    }
+
+    /// the shapes of the markers at the start and end of a line or arc
+    pub(crate) fn get_marker_shapes(&self, settings: &Settings) -> Vec<Shape> {
+        self.get_markers()
+            .into_iter()
+            .filter_map(|(feature, _, at, direction)| feature.shape(at, direction, settings))
+            .collect()
+    }
+
+    /// the features at the start and end of a line or arc,
+    /// with the point they are at and the direction of the stroke at that point
+    pub(crate) fn get_markers(&self) -> Vec<(&Feature, MarkerEnd, &Point, (f32, f32))> {
+        let (s, e, start_feature, end_feature, start_direction, end_direction) = match *self {
+            Element::Line(ref s, ref e, _, ref start_feature, ref end_feature, _) => {
+                let d = direction(s, e);
+                (s, e, start_feature, end_feature, d, d)
+            }
+            Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, _, ref start_feature, ref end_feature, _) => {
+                let center = arc_center(s, e, radius, *arc_flag == Major, sweep);
+                (s, e, start_feature, end_feature,
+                    arc_direction(&center, s, sweep), arc_direction(&center, e, sweep))
+            }
+            _ => return vec![],
+        };
+        let mut markers = vec![];
+        if *start_feature != Nothing {
+            markers.push((start_feature, MarkerEnd::Start, s, start_direction));
+        }
+        if *end_feature != Nothing {
+            markers.push((end_feature, MarkerEnd::End, e, end_direction));
+        }
+        markers
+    }
+
+    /// set the feature at `end` of a line or arc
+    pub(crate) fn set_feature(&mut self, end: MarkerEnd, feature: Feature) {
+        match *self {
+            Element::Line(_, _, _, ref mut start_feature, ref mut end_feature, _)
+            | Element::Arc(_, _, _, _, _, _, ref mut start_feature, ref mut end_feature, _) => match end {
+                MarkerEnd::Start => *start_feature = feature,
+                MarkerEnd::End => *end_feature = feature,
+            },
+            _ => (),
+        }
+    }
+
+    /// the cells of the grid this element is made from
+    pub fn cells(&self) -> &Cells {
+        match *self {
+            Element::Circle(_, _, ref cells) => cells,
+            Element::Line(_, _, _, _, _, ref cells) => cells,
+            Element::Arc(_, _, _, _, _, _, _, _, ref cells) => cells,
+            Element::Text(_, _, ref cells) => cells,
+        }
+    }
+
+    /// this element made from `cells`
+    pub fn with_cells(mut self, cells: Cells) -> Element {
+        match self {
+            Element::Circle(_, _, ref mut c) => *c = cells,
+            Element::Line(_, _, _, _, _, ref mut c) => *c = cells,
+            Element::Arc(_, _, _, _, _, _, _, _, ref mut c) => *c = cells,
+            Element::Text(_, _, ref mut c) => *c = cells,
+        }
+        self
+    }
+
+    /// this element made from its own cells and `cells`
+    pub fn merge_cells(&self, cells: &Cells) -> Element {
+        let merged = self.cells().merge(cells);
+        self.clone().with_cells(merged)
+    }
+
+    /// the class of the SVG element
+    pub(crate) fn class(&self) -> &'static str {
+        match *self {
+            Element::Circle(..) => "fg_stroke no_fill",
+            Element::Line(_, _, Solid, _, _, _) => "fg_stroke",
+            Element::Line(_, _, Dashed, _, _, _) => "fg_stroke dashed",
+            Element::Arc(..) => "fg_stroke no_fill",
+            Element::Text(..) => "fg_fill",
+        }
+    }
+
+    /// convert drawing element to SVG element,
+    /// tagged with the cells it is made from in `data-cells`
     pub fn to_svg(&self, settings: &Settings) -> SvgElement {
+        self.to_svg_in_group(settings, None)
+    }
+
+    /// convert drawing element to SVG element,
+    /// leaving out the class when it is `group_class` which is set on the parent `<g>`
+    pub(crate) fn to_svg_in_group(&self, settings: &Settings, group_class: Option<&str>) -> SvgElement {
+        let mut svg = self.to_svg_element(settings);
+        if group_class != Some(self.class()) {
+            svg = svg.set("class", self.class());
+        }
+        match self.cells().to_attribute() {
+            Some(cells) => svg.set("data-cells", cells),
+            None => svg,
+        }
+    }
+
+    fn to_svg_element(&self, settings: &Settings) -> SvgElement {
         match *self {
-            Element::Circle(ref c, r) => {
+            Element::Circle(ref c, r, _) => {
                 let svg_circle = SvgCircle::new()
-                    .set("class", "fg_stroke no_fill")
-                    .set("cx", c.x)
-                    .set("cy", c.y)
-                    .set("r", r);
+                    .set("cx", settings.round(c.x))
+                    .set("cy", settings.round(c.y))
+                    .set("r", settings.round(r));
 
                 SvgElement::Circle(svg_circle)
             }
-            Element::Line(ref s, ref e, ref stroke, ref start_feature, ref end_feature) => {
+            Element::Line(ref s, ref e, _, ref start_feature, ref end_feature, _) => {
                 let mut svg_line = SvgLine::new()
-                    .set("class", "fg_stroke")
-                    .set("x1", s.x)
-                    .set("y1", s.y)
-                    .set("x2", e.x)
-                    .set("y2", e.y);
+                    .set("x1", settings.round(s.x))
+                    .set("y1", settings.round(s.y))
+                    .set("x2", settings.round(e.x))
+                    .set("y2", settings.round(e.y));
 
-                if let Some(marker) = start_feature.get_marker(){
+                if let Some(marker) = start_feature.get_marker(settings){
                     svg_line.assign("marker-start", marker);
                 }
-                if let Some(marker) = end_feature.get_marker(){
+                if let Some(marker) = end_feature.get_marker(settings){
                     svg_line.assign("marker-end", marker);
                 }
-                match *stroke {
-                    Solid => (),
-                    Dashed => {
-                        svg_line.assign("class","fg_stroke dashed");
-                    }
-                };
                 SvgElement::Line(svg_line)
             }
-            Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, _, ref start_feature, ref end_feature) => {
+            Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, _, ref start_feature, ref end_feature, _) => {
                 let sweept = if sweep { "1" } else { "0" };
                 let arc_flag = match *arc_flag {
                     Major => "1",
@@ -351,27 +808,34 @@ impl Element {
                 };
                 let d = format!(
                     "M {} {} A {} {} 0 {} {} {} {}",
-                    s.x, s.y, radius, radius, arc_flag, sweept, e.x, e.y
+                    settings.round(s.x), settings.round(s.y),
+                    settings.round(radius), settings.round(radius),
+                    arc_flag, sweept,
+                    settings.round(e.x), settings.round(e.y)
                 );
                 let mut svg_arc = SvgPath::new()
-                    .set("class","fg_stroke no_fill")
                     .set("d", d);
-                if let Some(marker) = start_feature.get_marker(){
+                if let Some(marker) = start_feature.get_marker(settings){
                     svg_arc.assign("marker-start", marker);
                 }
-                if let Some(marker) = end_feature.get_marker(){
+                if let Some(marker) = end_feature.get_marker(settings){
                     svg_arc.assign("marker-end", marker);
                 }
                 SvgElement::Path(svg_arc)
             }
-            Element::Text(ref loc, ref string) => {
+            Element::Text(ref loc, ref string, _) => {
+                #[cfg(feature = "text-outlines")]
+                {
+                    if let Some(ref font) = settings.text_outlines {
+                        return SvgElement::Path(font.outline_text(loc, string, settings));
+                    }
+                }
                 let sx = loc.x as f32 * settings.text_width;
                 let sy = loc.y as f32 * settings.text_height + settings.text_height * 3.0 / 4.0;
                 let mut svg_text = SvgText::new()
-                    .set("class","fg_fill")
-                    .set("x", sx)
-                    .set("y", sy)
-                    .set("textLength", (width_xml_text(string) as f32) * settings.text_width);
+                    .set("x", settings.round(sx))
+                    .set("y", settings.round(sy))
+                    .set("textLength", settings.round((width_xml_text(string, settings) as f32) * settings.text_width));
                 let text_node = svg::node::Text::new(string.to_string());
                 svg_text.append(text_node);
                 SvgElement::Text(svg_text)
@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+use std::fmt::Write;
+use element::{num, ArcSegment, Cells, Feature, Stroke};
+use graph::{json_escape, json_number};
+use grid::{Grid, ViewBox};
+use loc::Loc;
+use loc_block::LocBlock;
+use point::Point;
+use renderer::{MarkerEnd, Renderer};
+use settings::Settings;
+
+/// the monospace font of excalidraw
+const FONT_FAMILY: u8 = 3;
+
+impl Grid {
+    /// get the diagram as an excalidraw scene, a `.excalidraw` file.
+    ///
+    /// The ids and seeds of the elements are numbered in the order they are drawn,
+    /// so the same diagram always gives the same scene
+    pub fn get_excalidraw(&self) -> String {
+        self.render(ExcalidrawRenderer::default())
+    }
+}
+
+/// the excalidraw scene writer
+#[derive(Default)]
+struct ExcalidrawRenderer {
+    settings: Settings,
+    /// the json of each element
+    elements: Vec<String>,
+    /// the last line or arc, written once its arrowheads are known
+    pending: Option<Linear>,
+}
+
+/// a line or arrow through `points`, relative to the first of them
+struct Linear {
+    points: Vec<Point>,
+    dashed: bool,
+    curved: bool,
+    start_arrowhead: Option<&'static str>,
+    end_arrowhead: Option<&'static str>,
+}
+
+impl ExcalidrawRenderer {
+    /// add an element of `kind` in the box at `x, y`,
+    /// `fields` are the fields only elements of the kind have
+    fn element(&mut self, kind: &str, (x, y, width, height): (f32, f32, f32, f32), dashed: bool, fields: &str) {
+        let n = self.elements.len() + 1;
+        let element = format!(
+            "{{\"id\":\"svgbob-{n}\",\"type\":\"{}\",\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"angle\":0,\
+             \"strokeColor\":\"{}\",\"backgroundColor\":\"transparent\",\"fillStyle\":\"solid\",\"strokeWidth\":{},\
+             \"strokeStyle\":\"{}\",\"roughness\":1,\"opacity\":100,\"groupIds\":[],\"frameId\":null,\
+             \"seed\":{n},\"version\":1,\"versionNonce\":{n},\"isDeleted\":false,\"boundElements\":null,\
+             \"updated\":1,\"link\":null,\"locked\":false,{}}}",
+            kind,
+            json_number(num(x)),
+            json_number(num(y)),
+            json_number(num(width)),
+            json_number(num(height)),
+            json_escape(&self.settings.stroke_color),
+            json_number(num(self.settings.stroke_width)),
+            if dashed { "dashed" } else { "solid" },
+            fields,
+            n = n
+        );
+        self.elements.push(element);
+    }
+
+    fn draw(&mut self, points: Vec<Point>, stroke: &Stroke, curved: bool) {
+        self.flush();
+        self.pending = Some(Linear {
+            points: points,
+            dashed: *stroke == Stroke::Dashed,
+            curved: curved,
+            start_arrowhead: None,
+            end_arrowhead: None,
+        });
+    }
+
+    /// add the pending line or arrow
+    fn flush(&mut self) {
+        if let Some(linear) = self.pending.take() {
+            let origin = linear.points[0].clone();
+            let points: Vec<String> = linear
+                .points
+                .iter()
+                .map(|p| format!("[{},{}]", json_number(num(p.x - origin.x)), json_number(num(p.y - origin.y))))
+                .collect();
+            let (min, max) = linear.points.iter().fold((origin.clone(), origin.clone()), |(min, max), p| {
+                (Point::new(min.x.min(p.x), min.y.min(p.y)), Point::new(max.x.max(p.x), max.y.max(p.y)))
+            });
+            let arrowhead = |arrowhead: Option<&str>| arrowhead.map_or("null".to_string(), |a| format!("\"{}\"", a));
+            let kind = if linear.start_arrowhead.is_some() || linear.end_arrowhead.is_some() {
+                "arrow"
+            } else {
+                "line"
+            };
+            let fields = format!(
+                "\"roundness\":{},\"points\":[{}],\"lastCommittedPoint\":null,\"startBinding\":null,\
+                 \"endBinding\":null,\"startArrowhead\":{},\"endArrowhead\":{}",
+                if linear.curved { "{\"type\":2}" } else { "null" },
+                points.join(","),
+                arrowhead(linear.start_arrowhead),
+                arrowhead(linear.end_arrowhead)
+            );
+            self.element(
+                kind,
+                (origin.x, origin.y, max.x - min.x, max.y - min.y),
+                linear.dashed,
+                &fields,
+            );
+        }
+    }
+}
+
+impl Renderer for ExcalidrawRenderer {
+    type Output = String;
+
+    fn begin_document(&mut self, _view_box: &ViewBox, settings: &Settings) {
+        self.settings = settings.clone();
+    }
+
+    fn line(&mut self, start: &Point, end: &Point, stroke: &Stroke, _cells: &Cells) {
+        self.draw(vec![start.clone(), end.clone()], stroke, false);
+    }
+
+    fn arc(&mut self, arc: &ArcSegment, stroke: &Stroke, _cells: &Cells) {
+        let geometry = arc.geometry();
+        // the curve goes through a point every 1/16 of a circle
+        let steps = ((geometry.sweep_angle.abs() / (PI / 8.0)).ceil() as usize).max(2);
+        let mut points: Vec<Point> = (0..steps)
+            .map(|i| geometry.point_at(geometry.start_angle + geometry.sweep_angle * i as f32 / steps as f32))
+            .collect();
+        points[0] = arc.start.clone();
+        points.push(arc.end.clone());
+        self.draw(points, stroke, true);
+    }
+
+    fn circle(&mut self, center: &Point, radius: f32, _cells: &Cells) {
+        self.flush();
+        self.element(
+            "ellipse",
+            (center.x - radius, center.y - radius, radius * 2.0, radius * 2.0),
+            false,
+            "\"roundness\":null",
+        );
+    }
+
+    fn text(&mut self, loc: &Loc, text: &str, _cells: &Cells) {
+        self.flush();
+        let lb = LocBlock {
+            loc: loc.clone(),
+            settings: self.settings.clone(),
+        };
+        let width = self.settings.str_width(text) as f32 * self.settings.text_width;
+        let fields = format!(
+            "\"roundness\":null,\"text\":\"{}\",\"fontSize\":{},\"fontFamily\":{},\"textAlign\":\"left\",\
+             \"verticalAlign\":\"top\",\"containerId\":null,\"originalText\":\"{}\",\"lineHeight\":1.25",
+            json_escape(text),
+            json_number(num(self.settings.font_size)),
+            FONT_FAMILY,
+            json_escape(text)
+        );
+        self.element("text", (lb.x0(), lb.y0(), width, self.settings.text_height), false, &fields);
+    }
+
+    fn marker(&mut self, feature: &Feature, end: MarkerEnd, _at: &Point, _direction: (f32, f32)) {
+        if let Some(ref mut linear) = self.pending {
+            match end {
+                MarkerEnd::Start => linear.start_arrowhead = arrowhead(feature),
+                MarkerEnd::End => linear.end_arrowhead = arrowhead(feature),
+            }
+        }
+    }
+
+    fn end_document(mut self) -> String {
+        self.flush();
+        let background = self.settings.background_color.clone().unwrap_or_else(|| "transparent".to_string());
+        let mut scene = String::from("{\n  \"type\": \"excalidraw\",\n  \"version\": 2,\n  \"source\": \"svgbob\",\n");
+        scene.push_str("  \"elements\": [");
+        for (i, element) in self.elements.iter().enumerate() {
+            let separator = if i == 0 { "\n" } else { ",\n" };
+            write!(scene, "{}    {}", separator, element).expect("writing to a string");
+        }
+        if !self.elements.is_empty() {
+            scene.push_str("\n  ");
+        }
+        writeln!(
+            scene,
+            "],\n  \"appState\": {{\"gridSize\": null, \"viewBackgroundColor\": \"{}\"}},\n  \"files\": {{}}\n}}",
+            json_escape(&background)
+        ).expect("writing to a string");
+        scene
+    }
+}
+
+/// the excalidraw arrowhead drawn for the feature
+fn arrowhead(feature: &Feature) -> Option<&'static str> {
+    match *feature {
+        Feature::Arrow => Some("triangle"),
+        Feature::ClearArrow => Some("arrow"),
+        Feature::Circle | Feature::OpenCircle | Feature::BigOpenCircle => Some("dot"),
+        Feature::Square => Some("bar"),
+        Feature::Nothing => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_excalidraw() {
+        let grid = Grid::from_str(".--> \"a\\b\"\n'", &Settings::default());
+        let scene = grid.get_excalidraw();
+        assert_eq!(scene, grid.get_excalidraw());
+        assert!(scene.starts_with("{\n  \"type\": \"excalidraw\",\n  \"version\": 2,\n  \"source\": \"svgbob\",\n"));
+        assert!(scene.contains("{\"id\":\"svgbob-3\",\"type\":\"arrow\",\"x\":8.0,\"y\":8.0,\"width\":20.0,\"height\":0.0,"));
+        assert!(scene.contains("\"points\":[[0.0,0.0],[20.0,0.0]],"));
+        assert!(scene.contains("\"startArrowhead\":null,\"endArrowhead\":\"triangle\"}"));
+        assert!(scene.contains("\"type\":\"line\",\"x\":8.0,\"y\":8.0,\"width\":4.0,\"height\":4.0,"));
+        assert!(scene.contains("\"roundness\":{\"type\":2},\"points\":[[0.0,0.0],"));
+        assert!(scene.contains("\"type\":\"text\",\"x\":40.0,\"y\":0.0,\"width\":24.0,\"height\":16.0,"));
+        assert!(scene.contains("\"text\":\"a\\\\b\","));
+        assert!(scene.ends_with("\"files\": {}\n}\n"));
+    }
+
+    #[test]
+    fn test_excalidraw_not_finite() {
+        let mut settings = Settings::default();
+        settings.stroke_width = std::f32::NAN;
+        settings.font_size = std::f32::INFINITY;
+        let scene = Grid::from_str("-- a", &settings).get_excalidraw();
+        assert!(scene.contains("\"strokeWidth\":null,"));
+        assert!(scene.contains("\"fontSize\":null,"));
+        assert!(!scene.contains("NaN"));
+        assert!(!scene.contains("inf"));
+    }
+}
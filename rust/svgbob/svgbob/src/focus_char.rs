@@ -11,7 +11,7 @@ use properties::{
 use point_block::PointBlock;
 use point::Point;
 use loc_block::LocBlock;
-use element::Element;
+use element::{Cells, Element};
 use fragments::Fragment::Text;
 use element::{line,dashed_line,circle_start_line,
     square_start_line, circle_open_line,big_circle_open_line, 
@@ -208,28 +208,28 @@ impl<'g> FocusChar<'g> {
     /// return the ehance circle elements and the consumed locations
     pub fn get_enhance_circle_elements(&self) -> (Vec<Element>, Vec<Loc>) {
         let (fragments, consumed_location) = self.get_enhance_circle_fragments();
-        let elements: Vec<Element> = fragments
-            .into_iter()
-            .map(|frag| self.to_element(frag))
-            .collect();
         let consumed_loc: Vec<Loc> = consumed_location
             .into_iter()
             .map(|location| self.loc.from_location(&location))
             .collect();
+        let elements: Vec<Element> = fragments
+            .into_iter()
+            .map(|frag| self.to_element(frag).with_cells(self.cells(&consumed_loc)))
+            .collect();
         (elements, consumed_loc)
     }
 
     /// return the ehance elements and the consumed locations
     pub fn get_enhance_elements(&self) -> (Vec<Element>, Vec<Loc>) {
         let (fragments, consumed_location) = self.get_enhance_fragments();
-        let elements: Vec<Element> = fragments
-            .into_iter()
-            .map(|frag| self.to_element(frag))
-            .collect();
         let consumed_loc: Vec<Loc> = consumed_location
             .into_iter()
             .map(|location| self.loc.from_location(&location))
             .collect();
+        let elements: Vec<Element> = fragments
+            .into_iter()
+            .map(|frag| self.to_element(frag).with_cells(self.cells(&consumed_loc)))
+            .collect();
         (elements, consumed_loc)
     }
 
@@ -238,9 +238,18 @@ impl<'g> FocusChar<'g> {
         let fragments = self.get_fragments();
         let elements: Vec<Element> = fragments
             .into_iter()
-            .map(|frag| self.to_element(frag))
+            .map(|frag| self.to_element(frag).with_cells(self.cells(&[])))
             .collect();
-        (elements)
+        elements
+    }
+
+    /// the cells spanned by this char and the `consumed` locations,
+    /// only recorded when `Settings::source_map` is set
+    fn cells(&self, consumed: &[Loc]) -> Cells {
+        if !self.grid.settings.source_map {
+            return Cells::none();
+        }
+        consumed.iter().fold(Cells::at(&self.loc), |cells, loc| cells.merge(&Cells::at(loc)))
     }
 
     fn is_satisfied(&self, can: &Can) -> bool {
@@ -324,7 +333,7 @@ impl<'g> FocusChar<'g> {
 
     /// get the fragements generated at this focus character and the
     /// consumed locations
-    fn get_fragments(&self) -> Vec<Fragment>{
+    pub(crate) fn get_fragments(&self) -> Vec<Fragment>{
         let character: Option<Characteristic> = self.ch.get_characteristic();
         let mut elm: Vec<Fragment> = vec![];
 
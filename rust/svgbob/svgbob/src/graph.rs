@@ -0,0 +1,688 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use element::{Element, Feature, Stroke};
+use grid::{xml_unescape, Grid};
+use point::{collinear, Point};
+use settings::Settings;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The meaning of a diagram: the boxes drawn in it and the lines connecting them.
+///
+/// The boxes are the closed rectangles, sharp or rounded,
+/// labeled with the text inside them.
+/// The edges follow the connected lines and arcs between the boxes,
+/// directed towards their arrowheads.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+/// a closed rectangle, the coordinates are in svg units
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Node {
+    /// the index in `Graph::nodes`
+    pub id: usize,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// whether any of the corners are rounded
+    pub rounded: bool,
+    /// the text inside the rectangle, a line for each row
+    pub label: String,
+}
+
+/// the lines and arcs connecting two nodes, an end that is not at a node is `None`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Edge {
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+    /// whether there is an arrowhead at `to`, the ends are interchangeable otherwise
+    pub directed: bool,
+    pub dashed: bool,
+}
+
+impl Grid {
+    /// detect the boxes of the diagram and the arrows connecting them
+    pub fn get_graph(&self) -> Graph {
+        let elements: Vec<Element> = self.get_elements().into_iter().flatten().collect();
+        let rects = find_rects(&elements);
+        let nodes = get_nodes(&rects, &elements, &self.settings);
+        let connectors = connectors(&elements, &rects);
+        let edges = edges(&connectors, &rects, &self.settings);
+        Graph { nodes: nodes, edges: edges }
+    }
+}
+
+impl Graph {
+    /// the graph in the DOT language of Graphviz,
+    /// the ends of edges which are not at a node are drawn as points
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for node in &self.nodes {
+            let style = if node.rounded { ", style=rounded" } else { "" };
+            writeln!(dot, "    n{} [label=\"{}\", shape=box{}];", node.id, dot_escape(&node.label), style)
+                .expect("writing to a string");
+        }
+        let mut points = 0;
+        for edge in &self.edges {
+            let mut end = |node: Option<usize>, dot: &mut String| match node {
+                Some(id) => format!("n{}", id),
+                None => {
+                    points += 1;
+                    writeln!(dot, "    p{} [label=\"\", shape=point];", points).expect("writing to a string");
+                    format!("p{}", points)
+                }
+            };
+            let from = end(edge.from, &mut dot);
+            let to = end(edge.to, &mut dot);
+            let mut attributes = vec![];
+            if !edge.directed {
+                attributes.push("dir=none");
+            }
+            if edge.dashed {
+                attributes.push("style=dashed");
+            }
+            let attributes = if attributes.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attributes.join(", "))
+            };
+            writeln!(dot, "    {} -> {}{};", from, to, attributes).expect("writing to a string");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// the graph as JSON: `{"nodes": [...], "edges": [...]}` with the fields of `Node` and `Edge`,
+    /// written the way `serde_json` writes them with the `serde` feature:
+    /// the coordinates always have a decimal point and NaN or infinite ones are `null`
+    pub fn to_json(&self) -> String {
+        let id = |node: Option<usize>| node.map_or("null".to_string(), |id| id.to_string());
+        let nodes: Vec<String> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                format!(
+                    "{{\"id\":{},\"x\":{},\"y\":{},\"width\":{},\"height\":{},\"rounded\":{},\"label\":\"{}\"}}",
+                    node.id,
+                    json_number(node.x),
+                    json_number(node.y),
+                    json_number(node.width),
+                    json_number(node.height),
+                    node.rounded,
+                    json_escape(&node.label)
+                )
+            })
+            .collect();
+        let edges: Vec<String> = self
+            .edges
+            .iter()
+            .map(|edge| {
+                format!(
+                    "{{\"from\":{},\"to\":{},\"directed\":{},\"dashed\":{}}}",
+                    id(edge.from), id(edge.to), edge.directed, edge.dashed
+                )
+            })
+            .collect();
+        format!("{{\"nodes\":[{}],\"edges\":[{}]}}", nodes.join(","), edges.join(","))
+    }
+}
+
+/// a closed rectangle between the corners `x0, y0` and `x1, y1`,
+/// the radii of the top left, top right, bottom left and bottom right corner are 0 when sharp
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Rect {
+    pub(crate) x0: f32,
+    pub(crate) y0: f32,
+    pub(crate) x1: f32,
+    pub(crate) y1: f32,
+    pub(crate) radii: [f32; 4],
+}
+
+impl Rect {
+    /// whether `arc` is one of the rounded corners
+    fn has_corner(&self, a: &Point, b: &Point) -> bool {
+        let corners = [
+            (self.x0, self.y0, 1.0, 1.0, self.radii[0]),
+            (self.x1, self.y0, -1.0, 1.0, self.radii[1]),
+            (self.x0, self.y1, 1.0, -1.0, self.radii[2]),
+            (self.x1, self.y1, -1.0, -1.0, self.radii[3]),
+        ];
+        corners.iter().any(|&(x, y, hdir, vdir, r)| r > 0.0 && is_corner_arc(a, b, x, y, hdir, vdir, r))
+    }
+
+    /// the distance of `p` to the outline, in cells
+    fn distance(&self, p: &Point, settings: &Settings) -> f32 {
+        let (dx, dy) = if p.x > self.x0 && p.x < self.x1 && p.y > self.y0 && p.y < self.y1 {
+            let dx = (p.x - self.x0).min(self.x1 - p.x);
+            let dy = (p.y - self.y0).min(self.y1 - p.y);
+            if dx / settings.text_width < dy / settings.text_height {
+                (dx, 0.0)
+            } else {
+                (0.0, dy)
+            }
+        } else {
+            ((self.x0 - p.x).max(p.x - self.x1).max(0.0), (self.y0 - p.y).max(p.y - self.y1).max(0.0))
+        };
+        (dx / settings.text_width).max(dy / settings.text_height)
+    }
+
+    fn area(&self) -> f32 {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+}
+
+/// the closed rectangles drawn with the elements,
+/// the smallest one at each top left corner ordered by their top and then their left side
+pub(crate) fn find_rects(elements: &[Element]) -> Vec<Rect> {
+    Sides::new(elements).find_rects()
+}
+
+/// the nodes of the rectangles, labeled with the text inside them
+pub(crate) fn get_nodes(rects: &[Rect], elements: &[Element], settings: &Settings) -> Vec<Node> {
+    let mut nodes: Vec<Node> = rects
+        .iter()
+        .enumerate()
+        .map(|(id, rect)| Node {
+            id: id,
+            x: rect.x0,
+            y: rect.y0,
+            width: rect.x1 - rect.x0,
+            height: rect.y1 - rect.y0,
+            rounded: rect.radii.iter().any(|&r| r > 0.0),
+            label: String::new(),
+        })
+        .collect();
+    label_nodes(&mut nodes, elements, settings);
+    nodes
+}
+
+/// whether the arc from `a` to `b` rounds the corner at `x, y`,
+/// `hdir` and `vdir` are the directions of the sides leaving the corner
+fn is_corner_arc(a: &Point, b: &Point, x: f32, y: f32, hdir: f32, vdir: f32, r: f32) -> bool {
+    let h = Point::new(x + hdir * r, y);
+    let v = Point::new(x, y + vdir * r);
+    (*a == h && *b == v) || (*a == v && *b == h)
+}
+
+/// the horizontal and vertical lines and the quarter arcs,
+/// the candidates for the sides and corners of rectangles
+struct Sides {
+    /// the x ranges of the horizontal lines by their y
+    horizontal: HashMap<u32, Vec<(f32, f32)>>,
+    /// the y ranges of the vertical lines by their x
+    vertical: HashMap<u32, Vec<(f32, f32)>>,
+    /// the endpoints and radius of the arcs spanning a quarter of a circle
+    arcs: Vec<(Point, Point, f32)>,
+    xs: Vec<f32>,
+    ys: Vec<f32>,
+}
+
+/// floats as keys, `-0.0` is the same as `0.0`
+fn key(v: f32) -> u32 {
+    (v + 0.0).to_bits()
+}
+
+impl Sides {
+    fn new(elements: &[Element]) -> Sides {
+        let mut sides = Sides {
+            horizontal: HashMap::new(),
+            vertical: HashMap::new(),
+            arcs: vec![],
+            xs: vec![],
+            ys: vec![],
+        };
+        for elm in elements {
+            match *elm {
+                Element::Line(ref s, ref e, _, _, _, _) if s.y == e.y && s.x != e.x => {
+                    sides.horizontal.entry(key(s.y)).or_insert_with(Vec::new).push((s.x.min(e.x), s.x.max(e.x)));
+                    sides.ys.push(s.y);
+                }
+                Element::Line(ref s, ref e, _, _, _, _) if s.x == e.x && s.y != e.y => {
+                    sides.vertical.entry(key(s.x)).or_insert_with(Vec::new).push((s.y.min(e.y), s.y.max(e.y)));
+                    sides.xs.push(s.x);
+                }
+                Element::Arc(ref s, ref e, _, _, _, _, _, _, _) => {
+                    let r = (s.x - e.x).abs();
+                    if r > 0.0 && r == (s.y - e.y).abs() {
+                        sides.arcs.push((s.clone(), e.clone(), r));
+                    }
+                }
+                _ => (),
+            }
+        }
+        for ranges in sides.horizontal.values_mut().chain(sides.vertical.values_mut()) {
+            ranges.sort_by(|a, b| a.partial_cmp(b).expect("no NaN"));
+        }
+        for values in vec![&mut sides.xs, &mut sides.ys] {
+            values.sort_by(|a, b| a.partial_cmp(b).expect("no NaN"));
+            values.dedup();
+        }
+        sides
+    }
+
+    /// whether the horizontal lines at `y` cover `a..b`
+    fn horizontal(&self, y: f32, a: f32, b: f32) -> bool {
+        covered(self.horizontal.get(&key(y)), a, b)
+    }
+
+    /// whether the vertical lines at `x` cover `a..b`
+    fn vertical(&self, x: f32, a: f32, b: f32) -> bool {
+        covered(self.vertical.get(&key(x)), a, b)
+    }
+
+    /// the radius of the corner at `x, y`, 0 when it is sharp.
+    /// `hdir` and `vdir` are the directions of the sides leaving the corner
+    fn corner(&self, x: f32, y: f32, hdir: f32, vdir: f32) -> Option<f32> {
+        if self.horizontal(y, x.min(x + hdir), x.max(x + hdir)) && self.vertical(x, y.min(y + vdir), y.max(y + vdir)) {
+            return Some(0.0);
+        }
+        self.arcs
+            .iter()
+            .find(|&&(ref a, ref b, r)| is_corner_arc(a, b, x, y, hdir, vdir, r))
+            .map(|&(_, _, r)| r)
+    }
+
+    /// the largest radius of the corners, the sides are shorter than the rectangle by up to this much
+    fn max_radius(&self) -> f32 {
+        self.arcs.iter().fold(0.0, |max, &(_, _, r)| max.max(r))
+    }
+
+    /// the smallest closed rectangle at each top left corner,
+    /// ordered by their top and then their left side
+    fn find_rects(&self) -> Vec<Rect> {
+        let max_r = self.max_radius();
+        let mut rects = vec![];
+        for &y0 in &self.ys {
+            for &x0 in &self.xs {
+                let top_left = match self.corner(x0, y0, 1.0, 1.0) {
+                    Some(r) => r,
+                    None => continue,
+                };
+                'right: for &x1 in self.xs.iter().filter(|&&x1| x1 > x0) {
+                    // the top side ends before this
+                    if !self.horizontal(y0, x0 + top_left, x1 - max_r) {
+                        break;
+                    }
+                    let top_right = match self.corner(x1, y0, -1.0, 1.0) {
+                        Some(r) => r,
+                        None => continue,
+                    };
+                    if !self.horizontal(y0, x0 + top_left, x1 - top_right) {
+                        continue;
+                    }
+                    for &y1 in self.ys.iter().filter(|&&y1| y1 > y0) {
+                        if !self.vertical(x0, y0 + top_left, y1 - max_r) {
+                            break;
+                        }
+                        let (bottom_left, bottom_right) =
+                            match (self.corner(x0, y1, 1.0, -1.0), self.corner(x1, y1, -1.0, -1.0)) {
+                                (Some(bl), Some(br)) => (bl, br),
+                                _ => continue,
+                            };
+                        if self.vertical(x0, y0 + top_left, y1 - bottom_left)
+                            && self.vertical(x1, y0 + top_right, y1 - bottom_right)
+                            && self.horizontal(y1, x0 + bottom_left, x1 - bottom_right)
+                        {
+                            rects.push(Rect {
+                                x0: x0,
+                                y0: y0,
+                                x1: x1,
+                                y1: y1,
+                                radii: [top_left, top_right, bottom_left, bottom_right],
+                            });
+                            break 'right;
+                        }
+                    }
+                }
+            }
+        }
+        rects
+    }
+}
+
+/// whether the sorted `ranges` cover `a..b` without gaps
+fn covered(ranges: Option<&Vec<(f32, f32)>>, a: f32, b: f32) -> bool {
+    if a >= b {
+        return true;
+    }
+    let mut reach = a;
+    for &(start, end) in ranges.map_or(&[][..], |ranges| &ranges[..]) {
+        if start > reach {
+            break;
+        }
+        reach = reach.max(end);
+        if reach >= b {
+            return true;
+        }
+    }
+    false
+}
+
+/// put the text inside each rectangle in its label, the text is in the smallest rectangle around it
+fn label_nodes(nodes: &mut [Node], elements: &[Element], settings: &Settings) {
+    let mut texts: Vec<Vec<(i32, i32, String)>> = vec![vec![]; nodes.len()];
+    for elm in elements {
+        if let Element::Text(ref loc, ref text, _) = *elm {
+            if let Some(id) = node_around(nodes, elm, settings) {
+                texts[id].push((loc.y, loc.x, xml_unescape(text).trim().to_string()));
+            }
+        }
+    }
+    for (node, mut texts) in nodes.iter_mut().zip(texts) {
+        texts.sort();
+        let mut label = String::new();
+        let mut row = None;
+        for (y, _, text) in texts {
+            if row == Some(y) {
+                label.push(' ');
+            } else if row.is_some() {
+                label.push('\n');
+            }
+            label.push_str(&text);
+            row = Some(y);
+        }
+        node.label = label;
+    }
+}
+
+/// the smallest node `elm` is inside of
+pub(crate) fn node_around(nodes: &[Node], elm: &Element, settings: &Settings) -> Option<usize> {
+    let (min, max) = elm.bounds(settings);
+    nodes
+        .iter()
+        .filter(|node| min.x > node.x && min.y > node.y && max.x < node.x + node.width && max.y < node.y + node.height)
+        .min_by(|a, b| (a.width * a.height).partial_cmp(&(b.width * b.height)).expect("no NaN"))
+        .map(|node| node.id)
+}
+
+/// the position of a point along a horizontal or vertical line
+type Along = fn(&Point) -> f32;
+
+/// the lines and arcs that are not the sides of the rectangles,
+/// the lines continuing past the sides are cut where they leave them
+pub(crate) fn connectors(elements: &[Element], rects: &[Rect]) -> Vec<Element> {
+    let mut connectors = vec![];
+    for elm in elements {
+        match *elm {
+            Element::Line(ref s, ref e, ref stroke, ref start_feature, ref end_feature, ref cells) => {
+                // the position along the line, and the ranges of the sides on the same line
+                let (along, sides): (Along, Vec<(f32, f32)>) = if s.y == e.y {
+                    let sides = rects
+                        .iter()
+                        .filter(|rect| rect.y0 == s.y || rect.y1 == s.y)
+                        .map(|rect| (rect.x0, rect.x1))
+                        .collect();
+                    (|p| p.x, sides)
+                } else if s.x == e.x {
+                    let sides = rects
+                        .iter()
+                        .filter(|rect| rect.x0 == s.x || rect.x1 == s.x)
+                        .map(|rect| (rect.y0, rect.y1))
+                        .collect();
+                    (|p| p.y, sides)
+                } else {
+                    connectors.push(elm.clone());
+                    continue;
+                };
+                let (a, b) = (along(s), along(e));
+                let mut pieces = vec![(a.min(b), a.max(b))];
+                for (lo, hi) in sides {
+                    pieces = pieces
+                        .into_iter()
+                        .flat_map(|(start, end)| vec![(start, end.min(lo)), (start.max(hi), end)])
+                        .filter(|&(start, end)| start < end)
+                        .collect();
+                }
+                // the point of the line at the position `t`
+                let at = |t: f32| {
+                    let f = (t - a) / (b - a);
+                    Point::new(s.x + (e.x - s.x) * f, s.y + (e.y - s.y) * f)
+                };
+                for (lo, hi) in pieces {
+                    let (start, end) = if a < b { (at(lo), at(hi)) } else { (at(hi), at(lo)) };
+                    let feature = |p: &Point, q: &Point, feature: &Feature| {
+                        if p == q { feature.clone() } else { Feature::Nothing }
+                    };
+                    connectors.push(Element::Line(
+                        start.clone(),
+                        end.clone(),
+                        stroke.clone(),
+                        feature(&start, s, start_feature),
+                        feature(&end, e, end_feature),
+                        cells.clone(),
+                    ));
+                }
+            }
+            Element::Arc(ref s, ref e, ..) if !rects.iter().any(|rect| rect.has_corner(s, e)) => {
+                connectors.push(elm.clone());
+            }
+            _ => (),
+        }
+    }
+    connectors
+}
+
+/// whether `p` is an endpoint of `elm` or on the line
+pub(crate) fn touches(elm: &Element, p: &Point) -> bool {
+    match *elm {
+        Element::Line(ref s, ref e, ..) => {
+            collinear(s, e, p)
+                && p.x >= s.x.min(e.x) && p.x <= s.x.max(e.x)
+                && p.y >= s.y.min(e.y) && p.y <= s.y.max(e.y)
+        }
+        Element::Arc(ref s, ref e, ..) => p == s || p == e,
+        _ => false,
+    }
+}
+
+/// the features at the start and end of a line or arc
+pub(crate) fn features(elm: &Element) -> (&Feature, &Feature) {
+    match *elm {
+        Element::Line(_, _, _, ref start_feature, ref end_feature, _)
+        | Element::Arc(_, _, _, _, _, _, ref start_feature, ref end_feature, _) => (start_feature, end_feature),
+        _ => (&Feature::Nothing, &Feature::Nothing),
+    }
+}
+
+fn is_arrowhead(feature: &Feature) -> bool {
+    *feature == Feature::Arrow || *feature == Feature::ClearArrow
+}
+
+/// the edges of the connected connectors,
+/// from each end without an arrowhead to each end with one
+fn edges(connectors: &[Element], rects: &[Rect], settings: &Settings) -> Vec<Edge> {
+    // the connectors touching each other are in the same component
+    let mut component: Vec<usize> = (0..connectors.len()).collect();
+    fn root(component: &mut [usize], i: usize) -> usize {
+        let mut i = i;
+        while component[i] != i {
+            component[i] = component[component[i]];
+            i = component[i];
+        }
+        i
+    }
+    for i in 0..connectors.len() {
+        for j in 0..i {
+            let (a, b) = (&connectors[i], &connectors[j]);
+            let connected = match (a.endpoints(), b.endpoints()) {
+                (Some((s1, e1)), Some((s2, e2))) => touches(a, s2) || touches(a, e2) || touches(b, s1) || touches(b, e1),
+                _ => false,
+            };
+            if connected {
+                let (ri, rj) = (root(&mut component, i), root(&mut component, j));
+                component[ri] = rj;
+            }
+        }
+    }
+    let mut order: Vec<usize> = vec![];
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..connectors.len() {
+        let r = root(&mut component, i);
+        if !members.contains_key(&r) {
+            order.push(r);
+        }
+        members.entry(r).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut edges = vec![];
+    for r in order {
+        let members = &members[&r];
+        let dashed = members.iter().any(|&i| match connectors[i] {
+            Element::Line(_, _, Stroke::Dashed, ..) | Element::Arc(_, _, _, _, _, Stroke::Dashed, ..) => true,
+            _ => false,
+        });
+        // the endpoints not touching the other connectors, with whether they have an arrowhead
+        let mut ends = vec![];
+        for &i in members {
+            let (s, e) = connectors[i].endpoints().expect("connectors are lines and arcs");
+            let (start_feature, end_feature) = features(&connectors[i]);
+            for &(p, feature) in &[(s, start_feature), (e, end_feature)] {
+                if !members.iter().any(|&j| j != i && touches(&connectors[j], p)) {
+                    ends.push((node_at(rects, p, settings), is_arrowhead(feature)));
+                }
+            }
+        }
+        let targets: Vec<Option<usize>> = ends.iter().filter(|end| end.1).map(|end| end.0).collect();
+        let sources: Vec<Option<usize>> = ends.iter().filter(|end| !end.1).map(|end| end.0).collect();
+        let mut pairs = vec![];
+        if targets.is_empty() {
+            for (i, &from) in sources.iter().enumerate() {
+                for &to in &sources[i + 1..] {
+                    pairs.push((from, to, false));
+                }
+            }
+        } else if sources.is_empty() {
+            // arrowheads at every end point both ways
+            for (i, &to) in targets.iter().enumerate() {
+                for (j, &from) in targets.iter().enumerate() {
+                    if i != j {
+                        pairs.push((from, to, true));
+                    }
+                }
+            }
+        } else {
+            for &to in &targets {
+                for &from in &sources {
+                    pairs.push((from, to, true));
+                }
+            }
+        }
+        for (from, to, directed) in pairs {
+            if from.is_some() || to.is_some() {
+                edges.push(Edge { from: from, to: to, directed: directed, dashed: dashed });
+            }
+        }
+    }
+    edges
+}
+
+/// the rectangle nearest to `p`, within a cell of its outline
+pub(crate) fn node_at(rects: &[Rect], p: &Point, settings: &Settings) -> Option<usize> {
+    rects
+        .iter()
+        .enumerate()
+        .map(|(id, rect)| (id, rect.distance(p, settings), rect.area()))
+        .filter(|&(_, distance, _)| distance <= 1.0)
+        .min_by(|a, b| (a.1, a.2).partial_cmp(&(b.1, b.2)).expect("no NaN"))
+        .map(|(id, _, _)| id)
+}
+
+/// escape the label for a quoted DOT string
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// `v` as a JSON number with a decimal point, `null` when it is NaN or infinite
+pub(crate) fn json_number(v: f32) -> String {
+    if v.is_finite() {
+        format!("{:?}", v)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// escape the label for a JSON string
+pub(crate) fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            ch if (ch as u32) < 0x20 => write!(escaped, "\\u{:04x}", ch as u32).expect("writing to a string"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_graph() {
+        let diagram = r#"
++------+   .-----.
+| Abc  +-->| Bar |
+| baz  |   '--+--'
++------+      |
+              v
+          +-------+
+          | Quux  |
+          +-------+
+"#;
+        let graph = Grid::from_str(diagram, &Settings::default()).get_graph();
+        let labels: Vec<&str> = graph.nodes.iter().map(|node| node.label.as_str()).collect();
+        assert_eq!(vec!["Abc\nbaz", "Bar", "Quux"], labels);
+        assert!(graph.nodes[1].rounded);
+        assert_eq!(
+            vec![
+                Edge { from: Some(0), to: Some(1), directed: true, dashed: false },
+                Edge { from: Some(1), to: Some(2), directed: true, dashed: false },
+            ],
+            graph.edges
+        );
+        assert_eq!(
+            "digraph {\n    n0 [label=\"Abc\\nbaz\", shape=box];\n    n1 [label=\"Bar\", shape=box, style=rounded];\n    \
+             n2 [label=\"Quux\", shape=box];\n    n0 -> n1;\n    n1 -> n2;\n}\n",
+            graph.to_dot()
+        );
+    }
+
+    #[test]
+    fn test_graph_json() {
+        let graph = Grid::from_str("+---+\n|\"a\"|---\n+---+", &Settings::default()).get_graph();
+        assert_eq!(
+            r#"{"nodes":[{"id":0,"x":4.0,"y":8.0,"width":32.0,"height":32.0,"rounded":false,"label":"a"}],"edges":[{"from":0,"to":null,"directed":false,"dashed":false}]}"#,
+            graph.to_json()
+        );
+        let mut graph = graph;
+        graph.nodes[0].x = 4.5;
+        graph.nodes[0].width = ::std::f32::NAN;
+        graph.nodes[0].height = ::std::f32::INFINITY;
+        assert!(graph.to_json().contains(r#""x":4.5,"y":8.0,"width":null,"height":null,"#));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_graph() {
+        use serde_json;
+        let graph = Grid::from_str("+---+\n|\"a\"|-->\n+---+", &Settings::default()).get_graph();
+        let json = serde_json::to_string(&graph).unwrap();
+        assert_eq!(
+            r#"{"nodes":[{"id":0,"x":4.0,"y":8.0,"width":32.0,"height":32.0,"rounded":false,"label":"a"}],"edges":[{"from":0,"to":null,"directed":true,"dashed":false}]}"#,
+            json
+        );
+        let parsed: Graph = serde_json::from_str(&json).unwrap();
+        assert_eq!(graph, parsed);
+        assert_eq!(json, graph.to_json());
+    }
+}
@@ -1,24 +1,36 @@
+use std::collections::HashSet;
+use std::fmt;
 use loc::Loc;
 use focus_char::FocusChar;
 use optimizer::Optimizer;
-use svg_element::SvgElement;
 use svg::node::element::SVG;
-use svg::Node;
-use svg::node::element::{
-    Definitions,
-    Marker,
-    Rectangle as SvgRect,
-    Style,
-    Circle as SvgCircle,
-    Polygon as SvgPolygon,
-    Group,
-};
-use element::Element;
+use element::{Cells, Element, width_xml_text};
+use point::Point;
 use pom::TextInput;
 use pom::parser::{sym,none_of};
-use settings::Settings;
-use unicode_width::UnicodeWidthChar;
+use settings::{is_valid_prefix, IdNamespace, Settings};
+use renderer::Renderer;
+use svg_renderer::SvgRenderer;
+use diagnostic::{Diagnostic, Span};
+use directive::apply_directives;
 use pom;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// the area of the grid shown in the svg, in the units of the svg
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ViewBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// formatted as the value of the `viewBox` attribute
+impl fmt::Display for ViewBox {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.x, self.y, self.width, self.height)
+    }
+}
 
 #[derive(Debug)]
 pub struct Grid {
@@ -29,6 +41,9 @@ pub struct Grid {
     /// This are text elements that are escaped and are not processed for diagram
     /// matching
     text_elm: Vec<(usize, usize, String)>,
+    /// the number of directive lines at the top of the input,
+    /// these are read into the settings instead of laid out
+    directive_rows: usize,
 }
 impl Grid {
     /// instantiate a grid from input ascii text
@@ -37,50 +52,90 @@ impl Grid {
     /// 2. 1-width, 2 bytes, single character  i.e. ö
     /// 3. 1-width, 3 bytes, single character  i.e. o͡͡͡
     pub fn from_str(s: &str, settings: &Settings) -> Grid {
+        let (grid, _diagnostics) = Grid::try_from_str(s, settings);
+        grid
+    }
+
+    /// instantiate a grid from input ascii text,
+    /// also reporting the parts of the input that could not be laid out
+    /// as the author most likely intended.
+    ///
+    /// The grid is always produced, the diagnostics only explain
+    /// why the rendered diagram may look off.
+    ///
+    /// The `%% svgbob:` directive lines at the top of the input override `settings`
+    /// and are not part of the grid, the rows of the diagnostics are still the lines of the input.
+    pub fn try_from_str(s: &str, settings: &Settings) -> (Grid, Vec<Diagnostic>) {
         let lines: Vec<&str> = s.lines().collect();
+        let mut settings = settings.clone();
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        let directive_rows = apply_directives(&lines, &mut settings, &mut diagnostics);
+        let lines = &lines[directive_rows..];
         let mut rows: Vec<Vec<String>> = Vec::with_capacity(lines.len());
         let mut text_elm: Vec<(usize, usize, String)> = vec![];
+        let mut layout_diagnostics: Vec<Diagnostic> = vec![];
         for (y, line) in lines.iter().enumerate() {
-            let (line, escaped_texts): (String, Vec<(usize, String)>) = exclude_escaped_text(line);
-            let mut row: Vec<String> = Vec::with_capacity(line.chars().count());
-            for (x, escaped) in escaped_texts {
-                text_elm.push((x, y, svg_escape(&escaped)));
-            }
-            for ch in line.chars() {
-                if let Some(1) = ch.width() {
-                    row.push(format!("{}", ch));
-                } else if let Some(2) = ch.width() {
-                    row.push(format!("{}", ch));
-                    // HACK: push a blank to the next cell,
-                    //in order to make this character twice as
-                    // big and aligns the next succeeding characters on
-                    // this row
-                    row.push(format!("\0"));
-                }
-                // if zero width char, append it to the previous string
-                else if let Some(0) = ch.width() {
-                    let prev: Option<String> = row.pop();
-                    match prev {
-                        Some(mut prev) => {
-                            prev.push(ch);
-                            row.push(prev);
-                        }
-                        None => (),
-                    }
-                }
-            }
+            let row = layout_line(line, y, &settings, &mut text_elm, &mut layout_diagnostics);
             rows.push(row);
         }
+        diagnostics.extend(layout_diagnostics.into_iter().map(|mut diagnostic| {
+            diagnostic.span.row += directive_rows;
+            diagnostic
+        }));
         let g = Grid {
-            settings: settings.clone(),
+            settings: settings,
             index: rows,
             text_elm: text_elm,
+            directive_rows: directive_rows,
         };
         // do the pre processing here
-        g.pre_process()
+        let mut g = g.pre_process();
+        if let IdNamespace::Prefix(ref prefix) = g.settings.id_namespace {
+            if !is_valid_prefix(prefix) {
+                // a prefix that would break out of the ids or the style sheet is replaced
+                diagnostics.push(Diagnostic::warning(
+                    Span::new(0, 0, 0),
+                    format!("invalid id prefix `{}` is replaced with a hash of the diagram", prefix),
+                ));
+                g.settings.id_namespace = IdNamespace::ContentHash;
+            }
+        }
+        if g.settings.id_namespace == IdNamespace::ContentHash {
+            // resolved once so the ids stay the same when the grid is edited
+            g.settings.id_namespace = IdNamespace::Prefix(g.content_hash());
+        }
+        (g, diagnostics)
     }
 
 
+    /// a hash of the cells, the escaped text and the settings as an id prefix
+    fn content_hash(&self) -> String {
+        // FNV-1a, the hash has to be the same on every platform and version
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |s: &str| {
+            for byte in s.bytes().chain(Some(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for row in &self.index {
+            for cell in row {
+                write(cell);
+            }
+            write("\n");
+        }
+        for &(x, y, ref text) in &self.text_elm {
+            write(&format!("{},{},{}", x, y, text));
+        }
+        write(&format!("{:?}", self.settings));
+        format!("bob{:08x}", hash as u32 ^ (hash >> 32) as u32)
+    }
+
+    /// the number of directive lines at the top of the input
+    pub fn directive_rows(&self) -> usize {
+        self.directive_rows
+    }
+
     pub fn rows(&self) -> usize {
         self.index.len()
     }
@@ -117,97 +172,200 @@ impl Grid {
 
     /// process the enhancing of circle elements
     /// this should be called before other elements are extracted from the grid
-    fn get_enhance_circle_elements(&self) -> (Vec<Vec<Vec<Element>>>, Vec<Loc>){
+    fn get_enhance_circle_elements(&self) -> (Vec<Vec<Vec<Element>>>, HashSet<Loc>){
         let mut rows: Vec<Vec<Vec<Element>>> = Vec::with_capacity(self.index.len());
-        let mut all_consumed_loc: Vec<Loc> = vec![];
-        for (y,line) in self.index.iter().enumerate() {
-            let mut row: Vec<Vec<Element>> = Vec::with_capacity(line.len());
-            for (x,_cell) in line.iter().enumerate() {
-                let loc = Loc::new(x as i32, y as i32);
-                let focus_char = self.get_focuschar(&loc);
-                let (cell_elements, consumed_loc) = focus_char.get_enhance_circle_elements();
-                all_consumed_loc.extend(consumed_loc);
-                row.push(cell_elements);
-            }
+        let mut all_consumed_loc: HashSet<Loc> = HashSet::new();
+        for y in 0..self.index.len() {
+            let (row, consumed_loc) = self.get_enhance_circle_row(y);
+            all_consumed_loc.extend(consumed_loc);
             rows.push(row);
         }
         (rows, all_consumed_loc)
     }
 
+    /// the circle elements of each cell in row `y`
+    /// and the locations they consumed
+    pub(crate) fn get_enhance_circle_row(&self, y: usize) -> (Vec<Vec<Element>>, Vec<Loc>) {
+        let line = &self.index[y];
+        let mut row: Vec<Vec<Element>> = Vec::with_capacity(line.len());
+        let mut all_consumed_loc: Vec<Loc> = vec![];
+        for (x,_cell) in line.iter().enumerate() {
+            let loc = Loc::new(x as i32, y as i32);
+            let focus_char = self.get_focuschar(&loc);
+            let (cell_elements, consumed_loc) = focus_char.get_enhance_circle_elements();
+            all_consumed_loc.extend(consumed_loc);
+            row.push(cell_elements);
+        }
+        (row, all_consumed_loc)
+    }
+
     /// process the enhanced circle elements first
     /// then process the generic enhancements
-    fn get_enhance_elements(&self) -> (Vec<Vec<Vec<Element>>>, Vec<Loc>){
+    fn get_enhance_elements(&self) -> (Vec<Vec<Vec<Element>>>, HashSet<Loc>){
         let (enhanced_circle_elm, circle_consumed_loc) = self.get_enhance_circle_elements();
         let mut rows: Vec<Vec<Vec<Element>>> = Vec::with_capacity(self.index.len());
         rows.extend(enhanced_circle_elm);
-        let mut all_consumed_loc: Vec<Loc> = vec![];
-        for (y,line) in self.index.iter().enumerate() {
-            let mut row: Vec<Vec<Element>> = Vec::with_capacity(line.len());
-            for (x,_cell) in line.iter().enumerate() {
-                let loc = Loc::new(x as i32, y as i32);
-                if !circle_consumed_loc.contains(&loc){
-                    let focus_char = self.get_focuschar(&loc);
-                    let (cell_elements, consumed_loc) = focus_char.get_enhance_elements();
-                    all_consumed_loc.extend(consumed_loc);
-                    row.push(cell_elements);
-                }
-            }
+        let mut all_consumed_loc: HashSet<Loc> = HashSet::new();
+        for y in 0..self.index.len() {
+            let (row, consumed_loc) = self.get_enhance_row(y, &circle_consumed_loc);
+            all_consumed_loc.extend(consumed_loc);
             rows.push(row);
         }
         all_consumed_loc.extend(circle_consumed_loc);
         (rows, all_consumed_loc)
     }
 
+    /// the generic enhancement elements of each cell in row `y`
+    /// that is not consumed by the circle enhancement,
+    /// and the locations they consumed
+    pub(crate) fn get_enhance_row(&self, y: usize, circle_consumed_loc: &HashSet<Loc>) -> (Vec<Vec<Element>>, Vec<Loc>) {
+        let line = &self.index[y];
+        let mut row: Vec<Vec<Element>> = Vec::with_capacity(line.len());
+        let mut all_consumed_loc: Vec<Loc> = vec![];
+        for (x,_cell) in line.iter().enumerate() {
+            let loc = Loc::new(x as i32, y as i32);
+            if !circle_consumed_loc.contains(&loc){
+                let focus_char = self.get_focuschar(&loc);
+                let (cell_elements, consumed_loc) = focus_char.get_enhance_elements();
+                all_consumed_loc.extend(consumed_loc);
+                row.push(cell_elements);
+            }
+        }
+        (row, all_consumed_loc)
+    }
+
     /// vector of each elements arranged in rows x columns
     /// returns all the elements and the consumed location
-    fn get_all_elements(&self) -> Vec<Vec<Vec<Element>>>{
+    pub(crate) fn get_all_elements(&self) -> Vec<Vec<Vec<Element>>>{
         let (enhanced_elms, enhance_consumed_locs) = self.get_enhance_elements();
         let mut rows: Vec<Vec<Vec<Element>>> = Vec::with_capacity(self.index.len());
         rows.extend(enhanced_elms);
-        for (y, line) in self.index.iter().enumerate() {
-            let mut row: Vec<Vec<Element>> = Vec::with_capacity(line.len());
-            for (x, _cell) in line.iter().enumerate() {
-                let loc = Loc::new(x as i32, y as i32);
-                if !enhance_consumed_locs.contains(&loc) {
-                    let focus_char = self.get_focuschar(&loc);
-                    let cell_elements = focus_char.get_elements();
-                    row.push(cell_elements);
-                }
-            }
-            rows.push(row);
+        for y in 0..self.index.len() {
+            rows.push(self.get_plain_row(y, &enhance_consumed_locs));
         }
         rows
     }
 
+    /// the elements of each cell in row `y` that is not consumed
+    /// by any of the enhancements
+    pub(crate) fn get_plain_row(&self, y: usize, enhance_consumed_locs: &HashSet<Loc>) -> Vec<Vec<Element>> {
+        let line = &self.index[y];
+        let mut row: Vec<Vec<Element>> = Vec::with_capacity(line.len());
+        for (x, _cell) in line.iter().enumerate() {
+            let loc = Loc::new(x as i32, y as i32);
+            if !enhance_consumed_locs.contains(&loc) {
+                let focus_char = self.get_focuschar(&loc);
+                let cell_elements = focus_char.get_elements();
+                row.push(cell_elements);
+            }
+        }
+        row
+    }
+
+    /// replace the lines `start..end` with the lines of `text`,
+    /// the rows below are moved up or down accordingly.
+    /// returns the number of rows inserted
+    pub(crate) fn replace_lines(&mut self, start: usize, end: usize, text: &str) -> usize {
+        let end = end.min(self.index.len());
+        let start = start.min(end);
+        let mut text_elm = vec![];
+        let mut diagnostics = vec![];
+        let mut rows: Vec<Vec<String>> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            rows.push(layout_line(line, start + i, &self.settings, &mut text_elm, &mut diagnostics));
+        }
+        let inserted = rows.len();
+        self.index.splice(start..end, rows);
+        for y in start..start + inserted {
+            self.index[y] = self.pre_process_row(y);
+        }
+        // move the escaped text below the replaced lines along with their rows
+        let mut moved_text_elm = vec![];
+        for (x, y, text) in self.text_elm.drain(..) {
+            if y < start {
+                moved_text_elm.push((x, y, text));
+            } else if y >= end {
+                moved_text_elm.push((x, y + inserted - (end - start), text));
+            }
+        }
+        moved_text_elm.extend(text_elm);
+        moved_text_elm.sort_by(|a, b| (a.1, a.0).cmp(&(b.1, b.0)));
+        self.text_elm = moved_text_elm;
+        inserted
+    }
+
+    /// the escaped texts as the cell of the opening quote and the svg escaped text
+    pub(crate) fn escaped_texts(&self) -> &[(usize, usize, String)] {
+        &self.text_elm
+    }
 
     fn get_escaped_text_elements(&self) -> Vec<Element> {
         self.text_elm
             .iter()
-            .map(|&(x, y, ref text)| Element::Text(Loc::new(x as i32, y as i32), text.to_owned()))
+            .map(|&(x, y, ref text)| {
+                let loc = Loc::new(x as i32, y as i32);
+                let cells = if self.settings.source_map {
+                    // the quotes are part of the escaped text
+                    let width = width_xml_text(text, &self.settings) as i32;
+                    Cells::new(&loc, &Loc::new(loc.x + width + 1, loc.y))
+                } else {
+                    Cells::none()
+                };
+                Element::Text(loc, text.to_owned(), cells)
+            })
             .collect()
     }
 
-    /// each component has its relative location retain
-    /// use this info for optimizing svg by checking closest neigbor
-    fn get_svg_nodes(&self) -> Vec<Vec<SvgElement>> {
-        let mut grouped_nodes = vec![];
-        let mut elements= self.get_all_elements();
+    /// the elements of the diagram in groups of connected elements,
+    /// the same as they are fed to a `Renderer`
+    pub fn get_elements(&self) -> Vec<Vec<Element>> {
+        self.optimize(self.get_all_elements())
+    }
+
+    pub(crate) fn optimize(&self, mut elements: Vec<Vec<Vec<Element>>>) -> Vec<Vec<Element>> {
         let text_elm = self.get_escaped_text_elements();
         elements.push(vec![text_elm]);
         let optimizer = Optimizer::new(elements);
-        let optimized_elements:Vec<Vec<Element>> = optimizer.optimize(&self.settings);
-        for group in optimized_elements {
-            let mut svg_group = vec![];
-            for elem in group{
-                let element: SvgElement = elem.to_svg(&self.settings);
-                svg_group.push(element);
-            }
-            grouped_nodes.push(svg_group);
-        }
-        grouped_nodes
+        optimizer.optimize(&self.settings)
     }
 
+    /// the whole grid, or the drawing and its padding when cropping
+    pub(crate) fn get_view_box(&self, elements: &[Vec<Element>]) -> ViewBox {
+        let (width, height) = self.get_size();
+        let whole = ViewBox {
+            x: 0.0,
+            y: 0.0,
+            width: self.settings.round(width),
+            height: self.settings.round(height),
+        };
+        let padding = match self.settings.crop {
+            Some(padding) => padding,
+            None => return whole,
+        };
+        let bounds = elements
+            .iter()
+            .flat_map(|group| group.iter())
+            .map(|elm| elm.bounds(&self.settings))
+            .fold(None, |acc: Option<(Point, Point)>, (min, max)| match acc {
+                Some((acc_min, acc_max)) => Some((
+                    Point::new(acc_min.x.min(min.x), acc_min.y.min(min.y)),
+                    Point::new(acc_max.x.max(max.x), acc_max.y.max(max.y)),
+                )),
+                None => Some((min, max)),
+            });
+        match bounds {
+            Some((min, max)) => ViewBox {
+                x: self.settings.round(min.x - padding),
+                y: self.settings.round(min.y - padding),
+                width: self.settings.round(max.x - min.x + 2.0 * padding),
+                height: self.settings.round(max.y - min.y + 2.0 * padding),
+            },
+            None => whole,
+        }
+    }
 
+    /// the size of the whole grid, this is the intrinsic size of the svg
+    /// unless it is cropped, see `get_svg_with_view_box`
     pub fn get_size(&self) -> (f32, f32) {
         let width = self.settings.text_width * self.columns() as f32;
         let height = self.settings.text_height * self.rows() as f32;
@@ -216,73 +374,43 @@ impl Grid {
 
     /// get the generated svg according to the settings specified
     pub fn get_svg(&self) -> SVG {
-        let group_nodes = self.get_svg_nodes();
-        let (width, height) = self.get_size();
-        let mut svg = SVG::new();
-
-        if let Some(ref id) = self.settings.id {
-            svg.assign("id", id.to_owned());
-        }
-        if let Some(ref class) = self.settings.class {
-            svg.assign("class", class.to_owned());
-        }
-        svg.assign("font-size", self.settings.font_size);
-        svg.assign("font-family", self.settings.font_family.to_owned());
-        svg.assign("width", width);
-        svg.assign("height", height);
-
-
-        svg.append(get_defs());
-        svg.append(get_styles(&self.settings));
-
-
-        let rect = SvgRect::new()
-            .set("x", 0)
-            .set("y", 0)
-            .set("class","backdrop")
-            .set("width", width)
-            .set("height", height);
-        svg.append(rect);
-
-        for group in group_nodes {
-            let mut svg_group = Group::new();
-            for node in group{
-                match node {
-                    SvgElement::Circle(circle) => {
-                        svg_group.append(circle);
-                    }
-                    SvgElement::Line(line) => {
-                        svg_group.append(line);
-                    }
-                    SvgElement::Path(path) => {
-                        svg_group.append(path);
-                    }
-                    SvgElement::Text(text) => {
-                        svg_group.append(text);
-                    }
-                }
-            }
-            svg.append(svg_group);
-        }
+        let (svg, _view_box) = self.get_svg_with_view_box();
         svg
     }
+
+    /// get the generated svg together with the area of the grid it shows,
+    /// the size of the view box is the intrinsic size of the svg
+    pub fn get_svg_with_view_box(&self) -> (SVG, ViewBox) {
+        let mut renderer = SvgRenderer::new();
+        self.feed(self.get_all_elements(), &mut renderer);
+        let view_box = renderer.view_box.clone();
+        (renderer.end_document(), view_box)
+    }
+
     /// traverse each element of the grid and swap characters as needed
     fn pre_process(&self) -> Self {
         let mut new_index: Vec<Vec<String>> = vec![];
-        for (y,line) in self.index.iter().enumerate(){
-            let mut row: Vec<String> = vec![];
-            for (x,_) in line.iter().enumerate(){
-                let loc = &Loc::new(x as i32, y as i32);
-                let swap = self.swap_char(loc);
-                row.push(swap.to_string());
-            }
-            new_index.push(row);
+        for y in 0..self.index.len() {
+            new_index.push(self.pre_process_row(y));
         }
         Grid{
             settings: self.settings.clone(),
             index: new_index,
-            text_elm: self.text_elm.clone()
+            text_elm: self.text_elm.clone(),
+            directive_rows: self.directive_rows,
+        }
+    }
+
+    /// swap the characters of row `y`,
+    /// the swapping only looks at the cells in the same row
+    fn pre_process_row(&self, y: usize) -> Vec<String> {
+        let mut row: Vec<String> = vec![];
+        for x in 0..self.index[y].len() {
+            let loc = &Loc::new(x as i32, y as i32);
+            let swap = self.swap_char(loc);
+            row.push(swap.to_string());
         }
+        row
     }
 
     /// swap characters  - - - with ~~~~~
@@ -327,237 +455,135 @@ impl Grid {
 
 }
 
-fn get_defs() -> Definitions {
-    let mut defs = Definitions::new();
-    defs.append(arrow_marker());
-    defs.append(clear_arrow_marker());
-    defs.append(circle_marker());
-    defs.append(square_marker());
-    defs.append(open_circle_marker());
-    defs.append(big_open_circle_marker());
-    defs
-}
-
-fn get_styles(settings: &Settings) -> Style {
-    let style = format!(
-        r#"
-rect.backdrop {{
-    fill: {background_color};
-}}
-
-.fg_fill {{
-    fill: {stroke_color};
-}}
-
-
-.bg_fill {{
-    fill: {background_color};
-}}
-
-.fg_stroke {{
-    stroke: {stroke_color};
-    stroke-width: {stroke_width};
-}}
-
-.no_fill {{
-    fill: none;
-}}
-
-.dashed {{
-    stroke-dasharray: 5;
-}}
-
-
-text{{
-    fill: {stroke_color};
-}}
-
-line, path {{
-    stroke-linecap: round;
-    stroke-linejoin: miter;
-    stroke-opacity: 1;
-    fill-opacity: 1;
-}}
-
-
-
-
-tspan.head{{
-    fill: none;
-    stroke: none;
-}}
-    "#,
-        stroke_width = settings.stroke_width,
-        stroke_color = &settings.stroke_color,
-        background_color = &settings.background_color,
-    );
-    Style::new(style)
-        .set("type", "text/css")
-}
-
-fn arrow_marker() -> Marker {
-    let mut marker = Marker::new()
-        .set("id", "triangle")
-        .set("viewBox", "0 0 8 6")
-        .set("refX", 4)
-        .set("refY", 3)
-        .set("orient", "auto")
-        .set("markerWidth", 8)
-        .set("markerHeight", 8);
-
-    let path = SvgPolygon::new()
-        .set("points", "0,0 0,6 8,3 0,0")
-        .set("class", "fg_fill");
-
-    marker.append(path);
-    marker
-}
-
-fn clear_arrow_marker() -> Marker {
-    let mut marker = Marker::new()
-        .set("id", "clear_triangle")
-        .set("viewBox", "0 0 20 14")
-        .set("refX", 1)
-        .set("refY", 7)
-        .set("orient", "auto")
-        .set("markerWidth", 10)
-        .set("markerHeight", 10);
-
-    let path = SvgPolygon::new()
-        .set("points", "2,2 2,12 18,7 2,2")
-        .set("class", "bg_fill fg_stroke");
-
-    marker.append(path);
-    marker
+/// lay out a line of the input into the cells of row `y`,
+/// the escaped text found in the line is added to `text_elm`
+fn layout_line(
+    line: &str,
+    y: usize,
+    settings: &Settings,
+    text_elm: &mut Vec<(usize, usize, String)>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<String> {
+    let line = expand_tabs(line, settings);
+    let (line, escaped_texts): (String, Vec<(usize, String)>) = exclude_escaped_text(&line, settings);
+    let mut row: Vec<String> = Vec::with_capacity(line.len());
+    for (x, escaped) in escaped_texts {
+        text_elm.push((x, y, svg_escape(&escaped)));
+    }
+    let mut unterminated_quote: Option<usize> = None;
+    // each extended grapheme cluster is one cell,
+    // or 2 cells when it is a wide character
+    for cluster in line.graphemes(true) {
+        // `\0` is reserved as the filler cell of wide characters
+        if cluster == "\0" {
+            diagnostics.push(Diagnostic::error(
+                Span::cell(y, row.len()),
+                "NUL character is not allowed and is ignored".to_string(),
+            ));
+            continue;
+        }
+        // quotes that are still here were not matched by the escape parser
+        if cluster.starts_with('"') && unterminated_quote.is_none() {
+            unterminated_quote = Some(diagnostics.len());
+            diagnostics.push(Diagnostic::warning(
+                Span::cell(y, row.len()),
+                "unterminated `\"` escape, the rest of the line is not escaped".to_string(),
+            ));
+        }
+        match settings.cluster_width(cluster) {
+            Some(2) => {
+                row.push(cluster.to_string());
+                // HACK: push a blank to the next cell,
+                //in order to make this character twice as
+                // big and aligns the next succeeding characters on
+                // this row
+                row.push(format!("\0"));
+            }
+            Some(_) => {
+                row.push(cluster.to_string());
+            }
+            None => {
+                let ch = cluster.chars().next().unwrap_or('\0');
+                diagnostics.push(Diagnostic::warning(
+                    Span::cell(y, row.len()),
+                    format!("control character U+{:04X} is ignored", ch as u32),
+                ));
+            }
+        }
+    }
+    // the unterminated escape spans up to the end of the line
+    if let Some(i) = unterminated_quote {
+        diagnostics[i].span.end_column = row.len();
+    }
+    row
 }
 
-
-///   <marker id="dot" viewBox="0 0 10 10" refX="5" refY="5"
-///        markerWidth="5" markerHeight="5">
-///      <circle cx="5" cy="5" r="5" fill="red" />
-///    </marker>
-fn circle_marker() -> Marker {
-    let mut marker = Marker::new()
-        .set("id", "circle")
-        .set("viewBox", "0 0 20 20")
-        .set("refX", 10)
-        .set("refY", 10)
-        .set("orient", "auto")
-        .set("markerWidth", 5)
-        .set("markerHeight", 5);
-
-    let circle = SvgCircle::new()
-        .set("cx",10)
-        .set("cy",10)
-        .set("r",8)
-        .set("class", "fg_fill");
-    marker.append(circle);
-    marker
+/// escape the text to be used in svg,
+/// the `\0` filler cells of wide characters are removed.
+///
+/// The text is processed per grapheme cluster and only the base
+/// character of a cluster is ever replaced with an entity,
+/// so the combining characters of a cluster are never split off from it.
+pub fn svg_escape(arg: &str) -> String {
+    let mut escaped = String::with_capacity(arg.len());
+    for cluster in arg.graphemes(true) {
+        let mut chars = cluster.char_indices();
+        if let Some((_, base)) = chars.next() {
+            match base {
+                '>' => escaped.push_str("&gt;"),
+                '<' => escaped.push_str("&lt;"),
+                '&' => escaped.push_str("&amp;"),
+                '\'' => escaped.push_str("&#39;"),
+                '"' => escaped.push_str("&quot;"),
+                '\0' => (),
+                _ => escaped.push(base),
+            }
+        }
+        if let Some((i, _)) = chars.next() {
+            escaped.push_str(&cluster[i..]);
+        }
+    }
+    escaped
 }
 
-fn square_marker() -> Marker {
-    let mut marker = Marker::new()
-        .set("id", "square")
-        .set("viewBox", "0 0 20 20")
-        .set("refX", 10)
-        .set("refY", 10)
-        .set("orient", "auto")
-        .set("markerWidth", 5)
-        .set("markerHeight", 5);
-
-    let square = SvgRect::new()
-        .set("x",0)
-        .set("y",0)
-        .set("width",20)
-        .set("height",20)
-        .set("class", "fg_fill");
-    marker.append(square);
-    marker
+/// undo the entities of `svg_escape`
+pub(crate) fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
 }
 
-fn open_circle_marker() -> Marker {
-    let mut marker = Marker::new()
-        .set("id", "open_circle")
-        .set("viewBox", "0 0 20 20")
-        .set("refX", 10)
-        .set("refY", 10)
-        .set("orient", "auto")
-        .set("markerWidth", 10)
-        .set("markerHeight", 10);
-
-    let circle = SvgCircle::new()
-        .set("cx",10)
-        .set("cy",10)
-        .set("r",4)
-        .set("class", "bg_fill fg_stroke");
-    marker.append(circle);
-    marker
-}
-fn big_open_circle_marker() -> Marker {
-    let mut marker = Marker::new()
-        .set("id", "big_open_circle")
-        .set("viewBox", "0 0 40 40")
-        .set("refX", 20)
-        .set("refY", 20)
-        .set("orient", "auto")
-        .set("markerWidth", 20)
-        .set("markerHeight", 20);
-
-    let circle = SvgCircle::new()
-        .set("cx",20)
-        .set("cy",20)
-        .set("r",6)
-        .set("class", "bg_fill fg_stroke");
-    marker.append(circle);
-    marker
-}
-//copied from https://github.com/rust-lang/rust/blob/master/src/librustdoc/html/escape.rs
-//just adding for \0
-pub fn svg_escape(arg: &str) -> String {
-    use std::fmt;
-
-    /// Wrapper struct which will emit the HTML-escaped version of the contained
-    /// string when passed to a format string.
-    pub struct Escape<'a>(pub &'a str);
-
-    impl<'a> fmt::Display for Escape<'a> {
-        fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-            // Because the internet is always right, turns out there's not that many
-            // characters to escape: http://stackoverflow.com/questions/7381974
-            let Escape(s) = *self;
-            let pile_o_bits = s;
-            let mut last = 0;
-            for (i, ch) in s.bytes().enumerate() {
-                match ch as char {
-                    '<' | '>' | '&' | '\'' | '"' | '\0' => {
-                        fmt.write_str(&pile_o_bits[last..i])?;
-                        let s = match ch as char {
-                            '>' => "&gt;",
-                            '<' => "&lt;",
-                            '&' => "&amp;",
-                            '\'' => "&#39;",
-                            '"' => "&quot;",
-                            '\0' => "",
-                            _ => unreachable!(),
-                        };
-                        fmt.write_str(s)?;
-                        last = i + 1;
-                    }
-                    _ => {}
-                }
-            }
+/// the widest tab stop, larger tab widths are clamped to this
+pub const MAX_TAB_WIDTH: usize = 32;
 
-            if last < s.len() {
-                fmt.write_str(&pile_o_bits[last..])?;
-            }
-            Ok(())
+/// replace each tab with spaces up to the next tab stop,
+/// columns are counted in cell widths so wide characters
+/// before the tab are accounted for
+fn expand_tabs(line: &str, settings: &Settings) -> String {
+    let tab_width = settings.tab_width.min(MAX_TAB_WIDTH);
+    if tab_width == 0 || !line.contains('\t') {
+        return line.to_string();
+    }
+    let mut buffer = String::with_capacity(line.len());
+    let mut column = 0;
+    for cluster in line.graphemes(true) {
+        if cluster == "\t" {
+            let spaces = tab_width - column % tab_width;
+            buffer.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            buffer.push_str(cluster);
+            column += settings.cluster_width(cluster).unwrap_or(0);
         }
-    };
-    let escaped = Escape(arg);
-    format!("{}", escaped)
+    }
+    buffer
 }
 
-fn exclude_escaped_text(line: &str) -> (String, Vec<(usize, String)>) {
+/// blank out the escaped text in this line,
+/// returning the escaped text with the cell column it starts at
+fn exclude_escaped_text(line: &str, settings: &Settings) -> (String, Vec<(usize, String)>) {
     let mut input = TextInput::new(line);
     let parsed = line_parse().parse(&mut input);
     let mut buffer = String::new();
@@ -568,9 +594,9 @@ fn exclude_escaped_text(line: &str) -> (String, Vec<(usize, String)>) {
             for (start, end) in parsed {
                 let escaped = &line[start + 1..end];
                 let recons = &line[index..start];
-                text_elm.push((start, escaped.to_string()));
+                text_elm.push((settings.str_width(&line[..start]), escaped.to_string()));
                 buffer.push_str(recons);
-                buffer.push_str(&" ".repeat(end + 1 - start));
+                buffer.push_str(&" ".repeat(settings.str_width(&line[start..end + 1])));
                 index = end + 1;
             }
             buffer.push_str(&line[index..line.len()]);
@@ -596,6 +622,7 @@ fn line_parse() -> pom::parser::Parser<'static, char, Vec<(usize, usize)>> {
 mod test{
 
     use super::*;
+    use settings::Sizing;
 
     #[test]
     fn test_escaped_string() {
@@ -626,6 +653,283 @@ mod test{
         assert_eq!(input3.len(), recons.len());
     }
 
+    #[test]
+    fn test_expand_tabs() {
+        let mut settings = Settings::default();
+        assert_eq!("        |", expand_tabs("\t|", &settings));
+        settings.tab_width = 4;
+        assert_eq!("ab  |", expand_tabs("ab\t|", &settings));
+        assert_eq!("统  |", expand_tabs("统\t|", &settings));
+        assert_eq!("→   |", expand_tabs("→\t|", &settings));
+        settings.cjk_width = true;
+        assert_eq!("→  |", expand_tabs("→\t|", &settings));
+        settings.tab_width = 0;
+        assert_eq!("\t|", expand_tabs("\t|", &settings));
+        // the tab left in is then ignored like the other control characters
+        let (g, diagnostics) = Grid::try_from_str("\t|", &settings);
+        assert_eq!(1, g.columns());
+        assert_eq!(
+            vec![Diagnostic::warning(Span::cell(0, 0), "control character U+0009 is ignored".to_string())],
+            diagnostics
+        );
+        settings.tab_width = usize::max_value();
+        assert_eq!(
+            format!("{}|", " ".repeat(MAX_TAB_WIDTH)),
+            expand_tabs("\t|", &settings)
+        );
+    }
+
+    #[test]
+    fn test_cjk_width() {
+        let mut settings = Settings::default();
+        let g = Grid::from_str("○-", &settings);
+        assert_eq!(2, g.columns());
+        settings.cjk_width = true;
+        let g = Grid::from_str("○-", &settings);
+        assert_eq!(3, g.columns());
+    }
+
+    #[test]
+    fn test_tab_escaped_text_column() {
+        let g = Grid::from_str("\t\"foo\"", &Settings::default());
+        assert_eq!(vec![(8, 0, "foo".to_string())], g.text_elm);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let (_, diagnostics) = Grid::try_from_str("+--+\n|\u{7}\"ab |\n\0", &Settings::default());
+        assert_eq!(
+            vec![
+                Diagnostic::warning(Span::cell(1, 1), "control character U+0007 is ignored".to_string()),
+                Diagnostic::warning(
+                    Span::new(1, 1, 6),
+                    "unterminated `\"` escape, the rest of the line is not escaped".to_string()
+                ),
+                Diagnostic::error(Span::cell(2, 0), "NUL character is not allowed and is ignored".to_string()),
+            ],
+            diagnostics
+        );
+        let (_, diagnostics) = Grid::try_from_str("+--+ \"ok\"", &Settings::default());
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_diagnostic_cell_columns() {
+        // the columns are cells, after the tab stop and after the two cells of each wide character
+        let (_, diagnostics) = Grid::try_from_str("\t\u{1}x\n日本\u{1}x", &Settings::default());
+        let spans: Vec<Span> = diagnostics.iter().map(|d| d.span.clone()).collect();
+        assert_eq!(vec![Span::cell(0, 8), Span::cell(1, 4)], spans);
+        assert!(diagnostics[0].to_string().starts_with("warning: 1:9: "));
+    }
+
+    #[test]
+    fn test_grapheme_clusters() {
+        let g = Grid::from_str("\u{301}e\u{301}👨\u{200d}👩\u{200d}👧🇯🇵|", &Settings::default());
+        assert_eq!(
+            vec!["\u{301}", "e\u{301}", "👨\u{200d}👩\u{200d}👧", "\0", "🇯🇵", "\0", "|"],
+            g.index[0]
+        );
+    }
+
+    #[test]
+    fn test_svg_escape_keeps_clusters() {
+        assert_eq!("&amp;\u{301}&lt;e\u{301}", svg_escape("&\u{301}<e\u{301}\0"));
+        assert_eq!("👨\u{200d}👩\u{200d}👧", svg_escape("👨\u{200d}👩\u{200d}👧"));
+    }
+
+    #[test]
+    fn test_escaped_text_column() {
+        let g = Grid::from_str("统\"统\"|", &Settings::default());
+        assert_eq!(vec![(2, 0, "统".to_string())], g.text_elm);
+        assert_eq!(Some(&"|".to_string()), g.get(&Loc::new(6, 0)));
+    }
+
+    #[test]
+    fn test_directives() {
+        let input = "%% svgbob: stroke-width=1.5 color=red\n+--->  \"a";
+        let (g, diagnostics) = Grid::try_from_str(input, &Settings::default());
+        assert_eq!(1, g.directive_rows());
+        assert_eq!(1, g.rows());
+        assert_eq!(1.5, g.settings.stroke_width);
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(Span::new(0, 28, 37), diagnostics[0].span);
+        // the rows of the diagnostics are the lines of the input
+        assert_eq!(1, diagnostics[1].span.row);
+    }
+
+    #[test]
+    fn test_id_namespace() {
+        let mut settings = Settings::default();
+        settings.id_namespace = IdNamespace::Prefix("d1".to_string());
+        let svg = Grid::from_str("o--->", &settings).get_svg().to_string();
+        assert!(svg.contains(r#"id="d1-triangle""#));
+        assert!(svg.contains(r#"id="d1-big_open_circle""#));
+        assert!(svg.contains("url(#d1-triangle)"));
+        assert!(!svg.contains("url(#triangle)"));
+        // the style sheet only applies to the svg of the namespace
+        assert!(svg.contains(r#"class="bob d1""#));
+        assert!(svg.contains(".d1 .fg_stroke {"));
+        assert!(svg.contains(".d1 line, .d1 path {"));
+        assert!(!svg.contains("\n.fg_stroke {"));
+
+        assert_eq!(Ok(IdNamespace::Prefix("d-1_a".to_string())), IdNamespace::prefix("d-1_a"));
+        for prefix in &["", "1d", "-d", "d1\"", "d}</style><script>", "d 1"] {
+            assert!(IdNamespace::prefix(prefix).is_err());
+        }
+        settings.id_namespace = IdNamespace::Prefix("d\"><script>".to_string());
+        let (g, diagnostics) = Grid::try_from_str("o--->", &settings);
+        assert_eq!(1, diagnostics.len());
+        let svg = g.get_svg().to_string();
+        assert!(!svg.contains("<script>"));
+        assert!(svg.contains(r#"id="bob"#));
+
+        settings.id_namespace = IdNamespace::ContentHash;
+        let prefix = |input: &str, settings: &Settings| Grid::from_str(input, settings).settings.id_namespace;
+        assert_eq!(prefix("o--->", &settings), prefix("o--->", &settings));
+        assert_ne!(prefix("o--->", &settings), prefix("o---->", &settings));
+        let mut red = settings.clone();
+        red.stroke_color = "red".to_string();
+        assert_ne!(prefix("o--->", &settings), prefix("o--->", &red));
+    }
+
+    #[test]
+    fn test_inline_markers() {
+        let mut settings = Settings::default();
+        settings.inline_markers = true;
+        let svg = Grid::from_str("  o-->", &settings).get_svg().to_string();
+        assert!(!svg.contains("marker"));
+        assert!(svg.contains(r#"<circle class="bg_fill fg_stroke" cx="20" cy="8" r="4"/>"#));
+        // the arrowhead extends past the end of the line the same way the marker does
+        assert!(svg.contains(r#"<polygon class="fg_fill" points="36,2 52,8 36,14"/>"#));
+    }
+
+    #[test]
+    fn test_rect_shapes() {
+        let mut settings = Settings::default();
+        settings.rect_shapes = true;
+        let input = "+--+  .--.\n|  +->|  |\n+--+  '--'\n .-.\n '-+";
+        let svg = Grid::from_str(input, &settings).get_svg().to_string();
+        assert!(svg.contains(r#"<rect class="fg_stroke no_fill" height="32" width="24" x="4" y="8"/>"#));
+        assert!(svg.contains(r#"<rect class="fg_stroke no_fill" height="32" rx="4" ry="4" width="24" x="52" y="8"/>"#));
+        // the arrow attached to the sides is drawn on its own
+        assert!(svg.contains(r#"<line class="fg_stroke" marker-end="url(#triangle)" x1="28" x2="44" y1="24" y2="24"/>"#));
+        // the corners of the last loop are not all the same
+        assert_eq!(2, svg.matches("<rect class=\"fg_stroke").count());
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut settings = Settings::default();
+        settings.scale(1.05);
+        settings.compact = true;
+        settings.precision = 1;
+        let svg = Grid::from_str("+--+\n|  |\n+--+  .-.\n      '-'", &settings).get_svg().to_string();
+        assert!(svg.contains(".no_fill{fill:none}"));
+        assert!(svg.contains(r#"<g class="fg_stroke"><line x1="4.2" x2="4.2" y1="8.4" y2="16.8"/>"#.replace("><", ">\n<").as_str()));
+        // no number has more than one decimal place
+        let numbers = svg.split(|c: char| !(c.is_digit(10) || c == '.'));
+        assert!(numbers.filter(|n| n.parse::<f32>().is_ok()).all(|n| n.split('.').nth(1).map_or(0, |d| d.len()) <= 1));
+
+        // the precision is limited to what an f32 can show
+        settings.precision = 40;
+        let svg = Grid::from_str("+--+\n|  |\n+--+  .-.\n      '-'", &settings).get_svg().to_string();
+        assert!(!svg.contains("NaN") && !svg.contains("inf"));
+        assert!(svg.contains(r#"x1="4.2" x2="4.2" y1="8.4" y2="16.8""#));
+    }
+
+    #[test]
+    fn test_nan_scale() {
+        let mut settings = Settings::default();
+        settings.scale(::std::f32::NAN);
+        let svg = Grid::from_str("+--+\n|  |\n+--+", &settings).get_svg().to_string();
+        assert!(svg.contains("<svg"));
+    }
+
+    #[test]
+    fn test_sizing() {
+        let mut settings = Settings::default();
+        settings.sizing = Sizing::ViewBox;
+        let g = Grid::from_str("+--+\n|  |\n+--+", &settings);
+        assert_eq!((32.0, 48.0), g.get_size());
+        let svg = g.get_svg().to_string();
+        let svg_tag = &svg[..svg.find('>').unwrap()];
+        assert!(svg_tag.contains(r#"viewBox="0 0 32 48""#));
+        assert!(!svg_tag.contains("width"));
+
+        settings.sizing = Sizing::ViewBoxMaxWidth;
+        let svg = Grid::from_str("+--+\n|  |\n+--+", &settings).get_svg().to_string();
+        assert!(svg.contains(r#"style="max-width: 32px""#));
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut settings = Settings::default();
+        settings.crop = Some(4.0);
+        settings.background_color = None;
+        let input = "\n\n      +---+\n      |   |\n      +---+\n\n";
+        let (svg, view_box) = Grid::from_str(input, &settings).get_svg_with_view_box();
+        // the box spans from the middle of the cells at its corners
+        assert_eq!(ViewBox { x: 47.0, y: 35.0, width: 42.0, height: 42.0 }, view_box);
+        let svg = svg.to_string();
+        assert!(svg.contains(r#"viewBox="47 35 42 42""#));
+        assert!(!svg.contains("backdrop\""));
+
+        // nothing to crop to
+        let (_, view_box) = Grid::from_str("\n   \n", &settings).get_svg_with_view_box();
+        assert_eq!(0.0, view_box.x);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut settings = Settings::default();
+        settings.source_map = true;
+        let svg = Grid::from_str("\n  +---->\n \"a b\"", &settings).get_svg().to_string();
+        // the reduced line spans all of its cells
+        assert!(svg.contains(r#"data-cells="2,1,7,1""#));
+        // the escaped text includes its quotes
+        assert!(svg.contains(r#"data-cells="1,2,5,2""#));
+
+        let svg = Grid::from_str("\n  +---->", &Settings::default()).get_svg().to_string();
+        assert!(!svg.contains("data-cells"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_elements() {
+        use serde_json;
+        let elements = Grid::from_str("--> \"a<b\"", &Settings::default()).get_elements();
+        let json = serde_json::to_string(&elements).unwrap();
+        assert_eq!(
+            r#"[[{"Line":[{"x":0.0,"y":8.0},{"x":20.0,"y":8.0},"Solid","Nothing","Arrow",null]}],[{"Text":[{"x":4,"y":0},"a<b",null]}]]"#,
+            json
+        );
+        let parsed: Vec<Vec<Element>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(elements, parsed);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialize_cells() {
+        use serde_json;
+        let mut settings = Settings::default();
+        settings.source_map = true;
+        let elements = Grid::from_str("-->", &settings).get_elements();
+        let cells: Vec<&Cells> = elements.iter().flat_map(|group| group.iter().map(|elm| elm.cells())).collect();
+        let json = serde_json::to_string(&cells).unwrap();
+        let parsed: Vec<Cells> = serde_json::from_str(&json).unwrap();
+        assert_eq!(cells, parsed.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_compare_cells() {
+        let cells = Cells::new(&Loc::new(0, 0), &Loc::new(2, 0));
+        assert_ne!(cells, Cells::none());
+        assert_eq!(cells, cells.clone());
+        // the elements are compared without their cells
+        let line = ::element::line(&Point::new(0.0, 8.0), &Point::new(20.0, 8.0));
+        assert_eq!(line, line.clone().with_cells(cells));
+    }
+
     #[test]
     fn test_escaped_multiline_string() {
         let input3 = r#"The "qu/i/ck brown fox \njumps over the lazy do|g""#;
@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet};
+use directive::apply_directives;
+use grid::Grid;
+use element::Element;
+use loc::Loc;
+use renderer::Renderer;
+use settings::Settings;
+use svg::node::element::SVG;
+use svg_renderer::SvgRenderer;
+
+/// the number of rows above and below a cell the enhancements look at,
+/// `enhance_circle` matches circles spanning 3 rows on each side
+const REACH: usize = 3;
+
+/// A grid that is kept around between edits, used for live previews.
+///
+/// Only the rows within the reach of an edit are matched again,
+/// the elements of the other rows are reused from the previous render.
+pub struct IncrementalGrid {
+    /// the settings the grid was created with, before the directives are applied
+    settings: Settings,
+    /// the lines of the input, the grid is created again from them when the directives change
+    lines: Vec<String>,
+    grid: Grid,
+    /// the circle elements and the locations they consumed, per row
+    circle_rows: Vec<(Vec<Vec<Element>>, Vec<Loc>)>,
+    /// the enhanced elements and the locations they consumed, per row
+    enhance_rows: Vec<(Vec<Vec<Element>>, Vec<Loc>)>,
+    /// the elements of the cells not consumed by the enhancements, per row
+    plain_rows: Vec<Vec<Vec<Element>>>,
+    /// the markup of each `<g>` of the last render
+    groups: Vec<String>,
+}
+
+/// the result of rendering the grid after an edit
+pub struct Update {
+    /// the complete svg
+    pub svg: SVG,
+    /// index of the `<g>` groups in `svg` that were not in the previous render
+    pub added_groups: Vec<usize>,
+    /// index of the `<g>` groups of the previous render that are no longer in `svg`
+    pub removed_groups: Vec<usize>,
+}
+
+impl IncrementalGrid {
+    pub fn new(s: &str, settings: &Settings) -> IncrementalGrid {
+        let mut incremental = IncrementalGrid {
+            settings: settings.clone(),
+            lines: s.lines().map(|line| line.to_string()).collect(),
+            grid: Grid::from_str(s, settings),
+            circle_rows: vec![],
+            enhance_rows: vec![],
+            plain_rows: vec![],
+            groups: vec![],
+        };
+        incremental.match_all();
+        incremental
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// get the svg of the current content
+    pub fn get_svg(&self) -> SVG {
+        let mut renderer = SvgRenderer::new();
+        self.grid.feed(self.get_all_elements(), &mut renderer);
+        renderer.end_document()
+    }
+
+    /// replace the lines `start..end` of the input with the lines of `text` and render again.
+    /// An edit of the directive lines changes the settings,
+    /// the whole grid is then created and matched again
+    pub fn replace_lines(&mut self, start: usize, end: usize, text: &str) -> Update {
+        let end = end.min(self.lines.len());
+        let start = start.min(end);
+        self.lines.splice(start..end, text.lines().map(|line| line.to_string()));
+        let lines: Vec<&str> = self.lines.iter().map(|line| line.as_str()).collect();
+        let directive_rows = apply_directives(&lines, &mut self.settings.clone(), &mut vec![]);
+        if start < directive_rows.max(self.grid.directive_rows()) {
+            self.grid = Grid::from_str(&self.lines.join("\n"), &self.settings);
+            return self.match_all();
+        }
+
+        let start = start - directive_rows;
+        let end = end - directive_rows;
+        let inserted = self.grid.replace_lines(start, end, text);
+        let shift = inserted as i32 - (end - start) as i32;
+
+        // the rows below the edit are moved instead of matched again
+        let settings = &self.grid.settings;
+        for &mut (ref mut row, ref mut consumed) in self.circle_rows[end..].iter_mut() {
+            shift_row(row, consumed, shift, settings);
+        }
+        for &mut (ref mut row, ref mut consumed) in self.enhance_rows[end..].iter_mut() {
+            shift_row(row, consumed, shift, settings);
+        }
+        for row in self.plain_rows[end..].iter_mut() {
+            shift_row(row, &mut [], shift, settings);
+        }
+        self.circle_rows.splice(start..end, vec![(vec![], vec![]); inserted]);
+        self.enhance_rows.splice(start..end, vec![(vec![], vec![]); inserted]);
+        self.plain_rows.splice(start..end, vec![vec![]; inserted]);
+
+        self.update_rows(start, start + inserted);
+        self.render()
+    }
+
+    /// match all the rows of the grid and render them
+    fn match_all(&mut self) -> Update {
+        let rows = self.grid.rows();
+        self.circle_rows = vec![(vec![], vec![]); rows];
+        self.enhance_rows = vec![(vec![], vec![]); rows];
+        self.plain_rows = vec![vec![]; rows];
+        self.update_rows(0, rows);
+        self.render()
+    }
+
+    /// match the rows around the changed rows `start..end` again.
+    /// The circles are affected within `REACH` of the change,
+    /// the enhancements skipping the cells consumed by those circles within `2 * REACH`,
+    /// and the plain elements skipping the cells consumed by both within `3 * REACH`
+    fn update_rows(&mut self, start: usize, end: usize) {
+        let rows = self.grid.rows();
+        for y in start.saturating_sub(REACH)..(end + REACH).min(rows) {
+            self.circle_rows[y] = self.grid.get_enhance_circle_row(y);
+        }
+        for y in start.saturating_sub(2 * REACH)..(end + 2 * REACH).min(rows) {
+            let circle_consumed = self.consumed_near(y, false);
+            self.enhance_rows[y] = self.grid.get_enhance_row(y, &circle_consumed);
+        }
+        for y in start.saturating_sub(3 * REACH)..(end + 3 * REACH).min(rows) {
+            let all_consumed = self.consumed_near(y, true);
+            self.plain_rows[y] = self.grid.get_plain_row(y, &all_consumed);
+        }
+    }
+
+    /// the consumed locations of the rows within reach of row `y`
+    fn consumed_near(&self, y: usize, with_enhanced: bool) -> HashSet<Loc> {
+        let start = y.saturating_sub(REACH);
+        let end = (y + REACH + 1).min(self.grid.rows());
+        let mut consumed = HashSet::new();
+        for near in start..end {
+            consumed.extend(self.circle_rows[near].1.iter().cloned());
+            if with_enhanced {
+                consumed.extend(self.enhance_rows[near].1.iter().cloned());
+            }
+        }
+        consumed
+    }
+
+    /// the elements of each pass in the same arrangement as `Grid::get_all_elements`
+    fn get_all_elements(&self) -> Vec<Vec<Vec<Element>>> {
+        let mut rows: Vec<Vec<Vec<Element>>> = Vec::with_capacity(self.grid.rows() * 3);
+        rows.extend(self.circle_rows.iter().map(|&(ref row, _)| row.clone()));
+        rows.extend(self.enhance_rows.iter().map(|&(ref row, _)| row.clone()));
+        rows.extend(self.plain_rows.iter().cloned());
+        rows
+    }
+
+    /// render the svg and compare its groups with the previous render
+    fn render(&mut self) -> Update {
+        let mut renderer = SvgRenderer::new();
+        self.grid.feed(self.get_all_elements(), &mut renderer);
+        renderer.finish_groups();
+        let markups: Vec<String> = renderer.groups.iter().map(|g| g.to_string()).collect();
+        let added_groups = unmatched(&markups, &self.groups);
+        let removed_groups = unmatched(&self.groups, &markups);
+        self.groups = markups;
+        Update {
+            svg: renderer.end_document(),
+            added_groups: added_groups,
+            removed_groups: removed_groups,
+        }
+    }
+}
+
+/// move the elements and consumed locations of a row
+fn shift_row(row: &mut [Vec<Element>], consumed: &mut [Loc], shift: i32, settings: &Settings) {
+    if shift == 0 {
+        return;
+    }
+    for cell in row.iter_mut() {
+        for elm in cell.iter_mut() {
+            *elm = elm.shift_rows(shift, settings);
+        }
+    }
+    for loc in consumed.iter_mut() {
+        loc.y += shift;
+    }
+}
+
+/// index of the groups in `groups` which has no counterpart in `other`
+fn unmatched(groups: &[String], other: &[String]) -> Vec<usize> {
+    let mut available: HashMap<&str, usize> = HashMap::new();
+    for g in other {
+        *available.entry(g).or_insert(0) += 1;
+    }
+    let mut unmatched = vec![];
+    for (i, g) in groups.iter().enumerate() {
+        let matched = match available.get_mut(g.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        };
+        if !matched {
+            unmatched.push(i);
+        }
+    }
+    unmatched
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use settings::IdNamespace;
+
+    const DIAGRAM: &str = r#"
+    +------+   +-----+
+    | Foo  +-->| Bar |
+    +------+   +-----+
+         .-.
+        (   )  "escaped"
+         `-'
+    .--------.
+    | Baz    |
+    '--------'
+"#;
+
+    /// a circle spanning 7 rows, the cells `REACH` rows above and below its center are part of it
+    const CIRCLE: &str = r#"
+      ______
+    ,'      `.
+   /          \
+  |            |---->
+  |            |
+   \          /
+    `.______.'
++------+
+|      |
++------+
+"#;
+
+    fn full_render(incremental: &IncrementalGrid, text: &str) {
+        full_render_with(incremental, text, &Settings::default());
+    }
+
+    fn full_render_with(incremental: &IncrementalGrid, text: &str, settings: &Settings) {
+        let expected = Grid::from_str(text, settings).get_svg().to_string();
+        assert_eq!(expected, incremental.get_svg().to_string());
+    }
+
+    #[test]
+    fn test_replace_lines_matches_full_render() {
+        let mut lines: Vec<&str> = DIAGRAM.lines().collect();
+        let mut incremental = IncrementalGrid::new(DIAGRAM, &Settings::default());
+        full_render(&incremental, DIAGRAM);
+
+        // insert rows above the circle
+        let update = incremental.replace_lines(4, 4, "    |  |\n    v  v");
+        lines.splice(4..4, vec!["    |  |", "    v  v"]);
+        full_render(&incremental, &lines.join("\n"));
+        assert!(!update.added_groups.is_empty());
+
+        // remove the top box
+        incremental.replace_lines(1, 4, "");
+        lines.splice(1..4, vec![]);
+        full_render(&incremental, &lines.join("\n"));
+
+        // edit a single row without changing the number of rows
+        let update = incremental.replace_lines(6, 7, "    | Quux   |");
+        lines.splice(6..7, vec!["    | Quux   |"]);
+        full_render(&incremental, &lines.join("\n"));
+        assert!(!update.removed_groups.is_empty());
+        assert!(update.added_groups.len() < incremental.groups.len());
+    }
+
+    #[test]
+    fn test_replace_lines_below_directives() {
+        let text = format!("%% svgbob: stroke-width=3{}", DIAGRAM);
+        let mut lines: Vec<&str> = text.lines().collect();
+        let mut incremental = IncrementalGrid::new(&text, &Settings::default());
+        full_render(&incremental, &text);
+
+        // the first row of the grid is right below the directive
+        incremental.replace_lines(1, 1, "  --->");
+        lines.insert(1, "  --->");
+        full_render(&incremental, &lines.join("\n"));
+
+        // an edit starting in the directive lines
+        incremental.replace_lines(0, 2, "%% svgbob: stroke=red");
+        lines.splice(0..2, vec!["%% svgbob: stroke=red"]);
+        full_render(&incremental, &lines.join("\n"));
+        assert_eq!("red", incremental.grid().settings.stroke_color);
+        assert_eq!(2.0, incremental.grid().settings.stroke_width);
+    }
+
+    #[test]
+    fn test_replace_lines_changes_directives() {
+        let mut lines: Vec<&str> = DIAGRAM.lines().collect();
+        let mut incremental = IncrementalGrid::new(DIAGRAM, &Settings::default());
+
+        // a directive added above the diagram
+        incremental.replace_lines(0, 0, "%% svgbob: stroke-width=3");
+        lines.insert(0, "%% svgbob: stroke-width=3");
+        full_render(&incremental, &lines.join("\n"));
+        assert_eq!(1, incremental.grid().directive_rows());
+
+        // a row added right below the directive becomes a directive
+        incremental.replace_lines(1, 1, "%% svgbob: background=none");
+        lines.insert(1, "%% svgbob: background=none");
+        full_render(&incremental, &lines.join("\n"));
+        assert_eq!(2, incremental.grid().directive_rows());
+
+        // the directive lines removed
+        incremental.replace_lines(0, 2, "");
+        lines.splice(0..2, vec![]);
+        full_render(&incremental, &lines.join("\n"));
+        assert_eq!(0, incremental.grid().directive_rows());
+    }
+
+    #[test]
+    fn test_replace_lines_content_hash() {
+        let mut settings = Settings::default();
+        settings.id_namespace = IdNamespace::ContentHash;
+        let mut lines: Vec<&str> = DIAGRAM.lines().collect();
+        let mut incremental = IncrementalGrid::new(DIAGRAM, &settings);
+        let namespace = incremental.grid().settings.id_namespace.clone();
+        assert_eq!(Grid::from_str(DIAGRAM, &settings).settings.id_namespace, namespace);
+
+        // the ids keep the prefix of the first content, so the unchanged groups stay the same
+        let update = incremental.replace_lines(2, 3, "    | Fooo +-->| Bar |");
+        lines.splice(2..3, vec!["    | Fooo +-->| Bar |"]);
+        assert_eq!(namespace, incremental.grid().settings.id_namespace);
+        let mut resolved = settings.clone();
+        resolved.id_namespace = namespace.clone();
+        full_render_with(&incremental, &lines.join("\n"), &resolved);
+        assert!(update.added_groups.len() < incremental.groups.len());
+
+        // the settings changed by a directive give a new prefix
+        incremental.replace_lines(0, 0, "%% svgbob: stroke=red");
+        lines.insert(0, "%% svgbob: stroke=red");
+        full_render_with(&incremental, &lines.join("\n"), &settings);
+        assert_ne!(namespace, incremental.grid().settings.id_namespace);
+    }
+
+    #[test]
+    fn test_replace_lines_rect_shapes() {
+        let mut settings = Settings::default();
+        settings.rect_shapes = true;
+        let mut lines: Vec<&str> = DIAGRAM.lines().collect();
+        let mut incremental = IncrementalGrid::new(DIAGRAM, &settings);
+        full_render_with(&incremental, DIAGRAM, &settings);
+
+        // the right side of the bottom box is broken then drawn again
+        incremental.replace_lines(8, 9, "    | Baz     ");
+        lines.splice(8..9, vec!["    | Baz     "]);
+        full_render_with(&incremental, &lines.join("\n"), &settings);
+        incremental.replace_lines(8, 9, "    | Baz    |");
+        lines.splice(8..9, vec!["    | Baz    |"]);
+        full_render_with(&incremental, &lines.join("\n"), &settings);
+        assert!(incremental.get_svg().to_string().contains("<rect class=\"fg_stroke no_fill\""));
+    }
+
+    #[test]
+    fn test_replace_lines_reach() {
+        let mut lines: Vec<&str> = CIRCLE.lines().collect();
+        let mut incremental = IncrementalGrid::new(CIRCLE, &Settings::default());
+        full_render(&incremental, CIRCLE);
+        assert!(incremental.get_svg().to_string().contains("r=\"48\""));
+
+        // the top and bottom rows of the circle are `REACH` rows from its center
+        for &(y, broken) in &[(1, "      ___ __"), (7, "    `.___ __.'")] {
+            let row = lines[y];
+            incremental.replace_lines(y, y + 1, broken);
+            lines[y] = broken;
+            full_render(&incremental, &lines.join("\n"));
+            assert!(!incremental.get_svg().to_string().contains("r=\"48\""));
+
+            incremental.replace_lines(y, y + 1, row);
+            lines[y] = row;
+            full_render(&incremental, &lines.join("\n"));
+            assert!(incremental.get_svg().to_string().contains("r=\"48\""));
+        }
+
+        // the rows moved past the reach of the edit keep their elements
+        incremental.replace_lines(0, 0, "\n\n\n\n");
+        lines.splice(0..0, vec![""; 4]);
+        full_render(&incremental, &lines.join("\n"));
+        incremental.replace_lines(13, 14, "");
+        lines.splice(13..14, vec![]);
+        full_render(&incremental, &lines.join("\n"));
+    }
+}
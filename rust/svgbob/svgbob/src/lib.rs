@@ -33,13 +33,37 @@
 extern crate svg;
 extern crate unicode_width;
 extern crate pom;
+extern crate unicode_segmentation;
+#[cfg(feature = "text-outlines")]
+extern crate ttf_parser;
+#[cfg(feature = "png")]
+extern crate png_encoder;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 
-pub use grid::Grid;
-pub use settings::Settings;
+pub use grid::{Grid, ViewBox};
+pub use graph::{Edge, Graph, Node};
+pub use element::{ArcFlag, ArcSegment, Cells, Element, Feature, Stroke};
+pub use loc::Loc;
+pub use color::is_color;
+pub use grid::MAX_TAB_WIDTH;
+pub use point::Point;
+pub use renderer::{MarkerEnd, Renderer};
+pub use svg_renderer::SvgRenderer;
+pub use settings::{IdNamespace, Settings, Sizing};
+pub use diagnostic::{Diagnostic, Severity, Span};
+pub use incremental::{IncrementalGrid, Update};
+#[cfg(feature = "text-outlines")]
+pub use outline::OutlineFont;
+#[cfg(feature = "png")]
+pub use png::{PngError, DEFAULT_DPI};
 use svg::node::element::SVG;
 
 mod optimizer;
 mod box_drawing;
+mod color;
 mod fragments;
 mod properties;
 mod settings;
@@ -55,6 +79,22 @@ mod focus_char;
 mod loc_block;
 mod enhance_circle;
 mod enhance;
+mod diagnostic;
+mod drawio;
+mod excalidraw;
+mod directive;
+mod incremental;
+mod merge;
+mod graph;
+mod pdf;
+mod renderer;
+mod svg_renderer;
+mod tikz;
+mod unicode;
+#[cfg(feature = "text-outlines")]
+mod outline;
+#[cfg(feature = "png")]
+mod png;
 
 /// generate an SVG from the ascii text input
 ///
@@ -70,6 +110,49 @@ pub fn to_svg(input: &str) -> SVG {
     Grid::from_str(&input, &Settings::default()).get_svg()
 }
 
+/// generate an SVG from the ascii text input,
+/// together with the problems found in the input
+///
+/// Usage:
+///
+/// ```
+/// let input = "--->  \"unterminated";
+/// let (svg, diagnostics) = svgbob::try_to_svg(input);
+/// for diagnostic in diagnostics {
+///     eprintln!("{}", diagnostic);
+/// }
+/// println!("svg: {}", svg);
+/// ```
+pub fn try_to_svg(input: &str) -> (SVG, Vec<Diagnostic>) {
+    let (grid, diagnostics) = Grid::try_from_str(&input, &Settings::default());
+    (grid.get_svg(), diagnostics)
+}
+
+/// generate a TikZ picture from the ascii text input,
+/// the arrow tips need `\usetikzlibrary{arrows.meta}`
+///
+/// Usage:
+///
+/// ```
+/// let input = "------->";
+/// println!("{}", svgbob::to_tikz(input));
+/// ```
+pub fn to_tikz(input: &str) -> String {
+    Grid::from_str(&input, &Settings::default()).get_tikz()
+}
+
+/// redraw the ascii text input with unicode box drawing characters
+///
+/// Usage:
+///
+/// ```
+/// let input = "------->";
+/// println!("{}", svgbob::to_unicode(input));
+/// ```
+pub fn to_unicode(input: &str) -> String {
+    Grid::from_str(&input, &Settings::default()).get_unicode()
+}
+
 
 
 
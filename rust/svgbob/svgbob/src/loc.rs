@@ -1,11 +1,14 @@
 use std::cmp::Ordering;
 use location::Location;
 use location::Direction::{Top,Bottom,Left,Right,TopLeft,TopRight,BottomLeft,BottomRight};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Location of Block relative to the Grid
 /// This the equivalent to the cell cation in the grid
 /// 0,0 is the top left most
-#[derive(Debug, Clone, PartialEq, PartialOrd, Eq)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Loc {
     pub x: i32,
     pub y: i32,
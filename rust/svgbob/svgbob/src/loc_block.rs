@@ -42,7 +42,7 @@ impl LocBlock {
     }
 
     /// x coordinate on increment of 1/4 of text width
-    fn x0(&self) -> f32 {
+    pub(crate) fn x0(&self) -> f32 {
         self.loc_x() * self.text_width()
     }
 
@@ -58,12 +58,12 @@ impl LocBlock {
         (self.loc_x() + 3.0 / 4.0) * self.text_width()
     }
 
-    fn x4(&self) -> f32 {
+    pub(crate) fn x4(&self) -> f32 {
         (self.loc_x() + 1.0) * self.text_width()
     }
 
     /// y coordinate on increment of 1/4 of text_height
-    fn y0(&self) -> f32 {
+    pub(crate) fn y0(&self) -> f32 {
         self.loc_y() * self.text_height()
     }
 
@@ -75,7 +75,7 @@ impl LocBlock {
         (self.loc_y() + 1.0 / 2.0) * self.text_height()
     }
 
-    fn y3(&self) -> f32 {
+    pub(crate) fn y3(&self) -> f32 {
         (self.loc_y() + 3.0 / 4.0) * self.text_height()
     }
 
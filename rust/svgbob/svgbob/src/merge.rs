@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use svg::Node;
+use svg::node::element::Path as SvgPath;
+use element::{Element, Feature, Stroke, ArcFlag};
+use point::Point;
+use settings::Settings;
+use svg_element::{shared_class, SvgElement};
+
+/// lines and arcs drawn one after the other, each starting where the previous one ends.
+/// Only the start of the first and the end of the last segment can have features
+struct Chain {
+    segments: Vec<Element>,
+}
+
+/// draw the connected lines and arcs of a group as paths:
+/// the chains without features that have the same stroke share one `<path>`,
+/// the chains with features each get their own since markers are only drawn
+/// at the ends of a path.
+/// The circles and text are converted as they are.
+/// Returns the class shared by all the elements in compact mode, which is left out of them
+pub(crate) fn merge_paths(elements: Vec<Element>, settings: &Settings) -> (Option<&'static str>, Vec<SvgElement>) {
+    let mut solid = vec![];
+    let mut dashed = vec![];
+    let mut others = vec![];
+    for elm in elements {
+        match stroke_of(&elm) {
+            Some(Stroke::Solid) => solid.push(elm),
+            Some(Stroke::Dashed) => dashed.push(elm),
+            None => others.push(elm),
+        }
+    }
+    let mut plain = vec![];
+    let mut featured = vec![];
+    let mut featured_circle = vec![];
+    for (segments, stroke) in vec![(solid, Stroke::Solid), (dashed, Stroke::Dashed)] {
+        let mut plain_chains = vec![];
+        for chain in build_chains(segments) {
+            let (start_feature, end_feature) = chain.features();
+            if *start_feature == Feature::Nothing && *end_feature == Feature::Nothing {
+                plain_chains.push(chain);
+            } else if is_open_circle(start_feature) || is_open_circle(end_feature) {
+                featured_circle.push((chain, stroke.clone()));
+            } else {
+                featured.push((chain, stroke.clone()));
+            }
+        }
+        if !plain_chains.is_empty() {
+            plain.push((plain_chains, stroke));
+        }
+    }
+
+    // the shapes drawn in place of the markers have classes of their own
+    let has_shapes = settings.inline_markers && !(featured.is_empty() && featured_circle.is_empty());
+    let group_class = if settings.compact && !has_shapes {
+        let mut classes = vec![];
+        classes.extend(plain.iter().map(|&(_, ref stroke)| stroke_class(stroke)));
+        classes.extend(featured.iter().map(|&(_, ref stroke)| stroke_class(stroke)));
+        classes.extend(others.iter().map(|elm| elm.class()));
+        classes.extend(featured_circle.iter().map(|&(_, ref stroke)| stroke_class(stroke)));
+        shared_class(&classes)
+    } else {
+        None
+    };
+
+    let mut merged = vec![];
+    for (chains, stroke) in plain {
+        let chains: Vec<&Chain> = chains.iter().collect();
+        merged.push(SvgElement::Path(path_element(&chains, &stroke, settings, group_class)));
+    }
+    for (chain, stroke) in featured {
+        merged.extend(chain.to_svg(&stroke, settings, group_class));
+    }
+    merged.extend(others.iter().map(|elm| elm.to_svg_in_group(settings, group_class)));
+    // put last to be infront of everything, the same as `Optimizer::arrange_elements`
+    for (chain, stroke) in featured_circle {
+        merged.extend(chain.to_svg(&stroke, settings, group_class));
+    }
+    (group_class, merged)
+}
+
+fn stroke_class(stroke: &Stroke) -> &'static str {
+    match *stroke {
+        Stroke::Solid => "fg_stroke no_fill",
+        Stroke::Dashed => "fg_stroke no_fill dashed",
+    }
+}
+
+fn stroke_of(elm: &Element) -> Option<Stroke> {
+    match *elm {
+        Element::Line(_, _, ref stroke, _, _, _) => Some(stroke.clone()),
+        Element::Arc(_, _, _, _, _, ref stroke, _, _, _) => Some(stroke.clone()),
+        _ => None,
+    }
+}
+
+fn is_open_circle(feature: &Feature) -> bool {
+    *feature == Feature::OpenCircle || *feature == Feature::BigOpenCircle
+}
+
+/// the feature at the start and end of a line or arc
+fn features(elm: &Element) -> (&Feature, &Feature) {
+    match *elm {
+        Element::Line(_, _, _, ref start_feature, ref end_feature, _) => (start_feature, end_feature),
+        Element::Arc(_, _, _, _, _, _, ref start_feature, ref end_feature, _) => (start_feature, end_feature),
+        _ => (&Feature::Nothing, &Feature::Nothing),
+    }
+}
+
+/// the same line or arc drawn from its end to its start
+fn reverse(elm: &Element) -> Element {
+    match *elm {
+        Element::Line(ref s, ref e, ref stroke, ref start_feature, ref end_feature, ref cells) => {
+            Element::Line(e.clone(), s.clone(), stroke.clone(), end_feature.clone(), start_feature.clone(), cells.clone())
+        }
+        Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, ref stroke, ref start_feature, ref end_feature, ref cells) => {
+            Element::Arc(e.clone(), s.clone(), radius, arc_flag.clone(), !sweep, stroke.clone(),
+                end_feature.clone(), start_feature.clone(), cells.clone())
+        }
+        _ => elm.clone(),
+    }
+}
+
+/// the segment `elm` oriented to start at `p`, if it can be joined there.
+/// The markers point along the segment, the segments with features keep their direction
+fn starting_at(elm: &Element, p: &Point) -> Option<Element> {
+    let (s, e) = elm.endpoints()?;
+    let (start_feature, end_feature) = features(elm);
+    if s == p && *start_feature == Feature::Nothing {
+        Some(elm.clone())
+    } else if e == p && *start_feature == Feature::Nothing && *end_feature == Feature::Nothing {
+        Some(reverse(elm))
+    } else {
+        None
+    }
+}
+
+/// the segment `elm` oriented to end at `p`, if it can be joined there
+fn ending_at(elm: &Element, p: &Point) -> Option<Element> {
+    let (s, e) = elm.endpoints()?;
+    let (start_feature, end_feature) = features(elm);
+    if e == p && *end_feature == Feature::Nothing {
+        Some(elm.clone())
+    } else if s == p && *start_feature == Feature::Nothing && *end_feature == Feature::Nothing {
+        Some(reverse(elm))
+    } else {
+        None
+    }
+}
+
+/// join the segments into chains,
+/// each segment is continued with the first segment not yet used that can be joined to it
+fn build_chains(segments: Vec<Element>) -> Vec<Chain> {
+    let mut at_point: HashMap<&Point, Vec<usize>> = HashMap::new();
+    for (i, elm) in segments.iter().enumerate() {
+        if let Some((s, e)) = elm.endpoints() {
+            at_point.entry(s).or_insert_with(Vec::new).push(i);
+            if s != e {
+                at_point.entry(e).or_insert_with(Vec::new).push(i);
+            }
+        }
+    }
+    let mut used = vec![false; segments.len()];
+    // the first unused segment that can be joined at `p`, oriented by `orient`
+    let take_at = |p: &Point, used: &mut Vec<bool>, orient: fn(&Element, &Point) -> Option<Element>| {
+        for &j in at_point.get(p)? {
+            if !used[j] {
+                if let Some(oriented) = orient(&segments[j], p) {
+                    used[j] = true;
+                    return Some(oriented);
+                }
+            }
+        }
+        None
+    };
+    let mut chains = vec![];
+    for i in 0..segments.len() {
+        if used[i] {
+            continue;
+        }
+        used[i] = true;
+        let mut chain = vec![segments[i].clone()];
+        loop {
+            let next = {
+                let last = chain.last().expect("chain is never empty");
+                match (features(last).1, last.endpoints()) {
+                    (&Feature::Nothing, Some((_, e))) => take_at(e, &mut used, starting_at),
+                    _ => None,
+                }
+            };
+            match next {
+                Some(next) => chain.push(next),
+                None => break,
+            }
+        }
+        loop {
+            let previous = {
+                let first = &chain[0];
+                match (features(first).0, first.endpoints()) {
+                    (&Feature::Nothing, Some((s, _))) => take_at(s, &mut used, ending_at),
+                    _ => None,
+                }
+            };
+            match previous {
+                Some(previous) => chain.insert(0, previous),
+                None => break,
+            }
+        }
+        chains.push(Chain { segments: chain });
+    }
+    chains
+}
+
+impl Chain {
+    fn features(&self) -> (&Feature, &Feature) {
+        let first = &self.segments[0];
+        let last = &self.segments[self.segments.len() - 1];
+        (features(first).0, features(last).1)
+    }
+
+    /// the path data of this chain, closed when it ends where it starts
+    fn path_data(&self, settings: &Settings) -> String {
+        let round = |v: f32| settings.round(v);
+        let mut d = String::new();
+        let mut start = None;
+        let mut end = None;
+        for elm in &self.segments {
+            match *elm {
+                Element::Line(ref s, ref e, _, _, _, _) => {
+                    if start.is_none() {
+                        d.push_str(&format!("M {} {}", round(s.x), round(s.y)));
+                        start = Some(s);
+                    }
+                    d.push_str(&format!(" L {} {}", round(e.x), round(e.y)));
+                    end = Some(e);
+                }
+                Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, _, _, _, _) => {
+                    if start.is_none() {
+                        d.push_str(&format!("M {} {}", round(s.x), round(s.y)));
+                        start = Some(s);
+                    }
+                    let large_arc = match *arc_flag {
+                        ArcFlag::Major => 1,
+                        ArcFlag::Minor => 0,
+                    };
+                    d.push_str(&format!(
+                        " A {} {} 0 {} {} {} {}",
+                        round(radius), round(radius), large_arc, sweep as u8, round(e.x), round(e.y)
+                    ));
+                    end = Some(e);
+                }
+                _ => (),
+            }
+        }
+        if self.segments.len() > 1 && start == end {
+            d.push_str(" Z");
+        }
+        d
+    }
+
+    /// the path of this chain and its markers
+    fn to_svg(&self, stroke: &Stroke, settings: &Settings, group_class: Option<&str>) -> Vec<SvgElement> {
+        let mut path = path_element(&[self], stroke, settings, group_class);
+        let (start_feature, end_feature) = self.features();
+        if let Some(marker) = start_feature.get_marker(settings) {
+            path.assign("marker-start", marker);
+        }
+        if let Some(marker) = end_feature.get_marker(settings) {
+            path.assign("marker-end", marker);
+        }
+        let mut svg = vec![SvgElement::Path(path)];
+        if settings.inline_markers {
+            // the joints have no features, only the ends of the chain
+            for elm in &self.segments {
+                svg.extend(elm.get_feature_shapes(settings));
+            }
+        }
+        svg
+    }
+}
+
+/// the chains drawn as one path,
+/// leaving out the class when it is `group_class` which is set on the parent `<g>`
+fn path_element(chains: &[&Chain], stroke: &Stroke, settings: &Settings, group_class: Option<&str>) -> SvgPath {
+    let d: Vec<String> = chains.iter().map(|chain| chain.path_data(settings)).collect();
+    let mut path = SvgPath::new().set("d", d.join(" "));
+    if group_class != Some(stroke_class(stroke)) {
+        path.assign("class", stroke_class(stroke));
+    }
+    let mut segments = chains.iter().flat_map(|chain| chain.segments.iter());
+    if let Some(first) = segments.next() {
+        let cells = segments.fold(first.cells().clone(), |cells, elm| cells.merge(elm.cells()));
+        if let Some(cells) = cells.to_attribute() {
+            path.assign("data-cells", cells);
+        }
+    }
+    path
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use element::{arc, line, arrow_line};
+
+    fn to_string(svg: Vec<SvgElement>) -> Vec<String> {
+        svg.iter().map(|elm| format!("{:?}", elm).trim().to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge_corner() {
+        let settings = Settings::default();
+        let elements = vec![
+            line(&Point::new(0.0, 8.0), &Point::new(20.0, 8.0)),
+            arc(&Point::new(24.0, 12.0), &Point::new(20.0, 8.0), 4.0),
+            line(&Point::new(24.0, 12.0), &Point::new(24.0, 40.0)),
+        ];
+        assert_eq!(
+            vec![r#"<path class="fg_stroke no_fill" d="M 0 8 L 20 8 A 4 4 0 0 1 24 12 L 24 40"/>"#],
+            to_string(merge_paths(elements, &settings).1)
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_markers_at_the_ends() {
+        let settings = Settings::default();
+        let elements = vec![
+            line(&Point::new(0.0, 0.0), &Point::new(0.0, 16.0)),
+            arrow_line(&Point::new(0.0, 16.0), &Point::new(16.0, 16.0)),
+            line(&Point::new(0.0, 16.0), &Point::new(0.0, 32.0)),
+        ];
+        assert_eq!(
+            vec![
+                r#"<path class="fg_stroke no_fill" d="M 0 16 L 0 32"/>"#,
+                r#"<path class="fg_stroke no_fill" d="M 0 0 L 0 16 L 16 16" marker-end="url(#triangle)"/>"#,
+            ],
+            to_string(merge_paths(elements, &settings).1)
+        );
+    }
+
+    #[test]
+    fn test_closed_loop() {
+        let settings = Settings::default();
+        let (a, b, c) = (Point::new(0.0, 0.0), Point::new(16.0, 0.0), Point::new(16.0, 16.0));
+        let elements = vec![line(&a, &b), line(&b, &c), line(&c, &a)];
+        assert_eq!(
+            vec![r#"<path class="fg_stroke no_fill" d="M 0 0 L 16 0 L 16 16 L 0 0 Z"/>"#],
+            to_string(merge_paths(elements, &settings).1)
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use element::Element;
 use element::Feature::{OpenCircle, BigOpenCircle};
 use loc::Loc;
+use point::Point;
 use settings::Settings;
 use element::Stroke;
 
@@ -26,13 +29,13 @@ impl Optimizer {
 
 
     // return the reduced element and the index of the matching element on this location
-    fn reduce(&self, elm1: &Element, loc2: &Loc) -> Option<(Vec<Element>, usize)> {
+    fn reduce(&self, elm1: &Element, loc2: &Loc, settings: &Settings) -> Option<(Vec<Element>, usize)> {
         // try all the elments of this location
         if let Some(elements2) = self.get(loc2){
             if elements2.len() > 0 {
                 for (i,elm2) in elements2.iter().enumerate(){
                     // use the element that can be reduced with
-                    if let Some(reduced) = elm1.reduce(&elm2){
+                    if let Some(reduced) = elm1.reduce(&elm2, settings){
                         let mut new_reduced = vec![];
                         new_reduced.push(reduced);
                         new_reduced.sort();
@@ -48,50 +51,50 @@ impl Optimizer {
     /// trace the and try to reduce this element against the elements
     /// at this location(loc),
     /// returns the reduced element, the location and index of the consumed element
-    fn trace_elements(&self, element: &Element, loc: &Loc) -> (Vec<Element>, Vec<(Loc, usize)>) {
+    fn trace_elements(&self, element: &Element, loc: &Loc, settings: &Settings) -> (Vec<Element>, Vec<(Loc, usize)>) {
         //trace to the right first
         let right = loc.right();
         let bottom = loc.bottom();
         let bottom_right = loc.bottom_right();
         let bottom_left = loc.bottom_left();
-        if let Some((all_reduced, elm_index)) = self.reduce(element, &right){
+        if let Some((all_reduced, elm_index)) = self.reduce(element, &right, settings){
             let mut all_consumed:Vec<(Loc, usize)> = vec![];
             let mut only_reduced = vec![];
             for reduced_elm in all_reduced{
-                let (reduced, consumed) = self.trace_elements(&reduced_elm, &right);
+                let (reduced, consumed) = self.trace_elements(&reduced_elm, &right, settings);
                 all_consumed.push((right.clone(), elm_index));
                 all_consumed.extend(consumed);
                 only_reduced = reduced;
             }
             (only_reduced, all_consumed)
         }
-        else if let Some((all_reduced, elm_index)) = self.reduce(element, &bottom){
+        else if let Some((all_reduced, elm_index)) = self.reduce(element, &bottom, settings){
             let mut all_consumed = vec![];
             let mut only_reduced = vec![];
             for reduced_elm in all_reduced{
-                let (reduced, consumed) = self.trace_elements(&reduced_elm, &bottom);
+                let (reduced, consumed) = self.trace_elements(&reduced_elm, &bottom, settings);
                 all_consumed.push((bottom.clone(), elm_index));
                 all_consumed.extend(consumed);
                 only_reduced = reduced;
             }
             (only_reduced, all_consumed)
         }
-        else if let Some((all_reduced, elm_index)) = self.reduce(element, &bottom_right){
+        else if let Some((all_reduced, elm_index)) = self.reduce(element, &bottom_right, settings){
             let mut all_consumed = vec![];
             let mut only_reduced = vec![];
             for reduced_elm in all_reduced{
-                let (reduced, consumed) = self.trace_elements(&reduced_elm, &bottom_right);
+                let (reduced, consumed) = self.trace_elements(&reduced_elm, &bottom_right, settings);
                 all_consumed.push((bottom_right.clone(), elm_index));
                 all_consumed.extend(consumed);
                 only_reduced = reduced;
             }
             (only_reduced, all_consumed)
         }
-        else if let Some((all_reduced, elm_index)) = self.reduce(element, &bottom_left){
+        else if let Some((all_reduced, elm_index)) = self.reduce(element, &bottom_left, settings){
             let mut all_consumed = vec![];
             let mut only_reduced = vec![];
             for reduced_elm in all_reduced{
-                let (reduced, consumed) = self.trace_elements(&reduced_elm, &bottom_left);
+                let (reduced, consumed) = self.trace_elements(&reduced_elm, &bottom_left, settings);
                 all_consumed.push((bottom_left.clone(),elm_index));
                 all_consumed.extend(consumed);
                 only_reduced = reduced;
@@ -105,20 +108,49 @@ impl Optimizer {
 
 
     /// grouped elements together that shares some end_points
-    fn group_elements<'e>(&self, elements: Vec<Element>) -> Vec<Vec<Element>> {
-        let mut consumed = vec![];
+    ///
+    /// Visiting the elements in order, an element joins the group
+    /// when it shares an end point with the elements already in the group.
+    /// The elements at an end point are looked up from a hash map and
+    /// the next element to visit is taken from a min-heap,
+    /// so this is O(n log n) instead of checking every pair of elements
+    fn group_elements(&self, elements: Vec<Element>) -> Vec<Vec<Element>> {
+        let mut at_point: HashMap<&Point, Vec<usize>> = HashMap::new();
+        for (i, elm) in elements.iter().enumerate() {
+            if let Some((s, e)) = elm.endpoints() {
+                at_point.entry(s).or_insert_with(Vec::new).push(i);
+                if s != e {
+                    at_point.entry(e).or_insert_with(Vec::new).push(i);
+                }
+            }
+        }
+        // the elements sharing an end point with element `i` visited after it
+        let connected_after = |i: usize, next: &mut BinaryHeap<Reverse<usize>>| {
+            if let Some((s, e)) = elements[i].endpoints() {
+                // a NaN point never equals itself so it is not found in the map
+                for p in &[s, e] {
+                    for &j in at_point.get(p).into_iter().flatten() {
+                        if j > i {
+                            next.push(Reverse(j));
+                        }
+                    }
+                }
+            }
+        };
+        let mut consumed = vec![false; elements.len()];
         let mut all_group = vec![];
         for (i, elm) in elements.iter().enumerate(){
-            if !consumed.contains(&i){
+            if !consumed[i]{
                 let mut cell_group = vec![];
                 cell_group.push(elm.clone());
-                consumed.push(i);
-                for (j, elm2) in elements.iter().enumerate(){
-                    if !consumed.contains(&j){
-                        if cell_group.iter().any(|e|e.shares_endpoints(&elm2)){
-                            cell_group.push(elm2.clone());
-                            consumed.push(j);
-                        }
+                consumed[i] = true;
+                let mut next = BinaryHeap::new();
+                connected_after(i, &mut next);
+                while let Some(Reverse(j)) = next.pop() {
+                    if !consumed[j]{
+                        cell_group.push(elements[j].clone());
+                        consumed[j] = true;
+                        connected_after(j, &mut next);
                     }
                 }
                 all_group.push(cell_group);
@@ -129,8 +161,8 @@ impl Optimizer {
 
     // TODO: order the elements in such a way that
     // the start -> end -> start chains nicely
-    pub fn optimize(&self, _settings: &Settings) -> Vec<Vec<Element>> {
-        let mut tracing_consumed_locs: Vec<(Loc,usize)> = vec![];
+    pub fn optimize(&self, settings: &Settings) -> Vec<Vec<Element>> {
+        let mut tracing_consumed_locs: HashSet<(Loc,usize)> = HashSet::new();
         let mut optimized = vec![];
 
         for (y, line) in self.elements.iter().enumerate() {
@@ -138,7 +170,7 @@ impl Optimizer {
                 let loc = &Loc::new(x as i32, y as i32);
                 for (elm_index, elm) in cell.iter().enumerate() {
                     if !tracing_consumed_locs.contains(&(loc.clone(),elm_index)){
-                        let (traced, consumed) = self.trace_elements(elm, loc);
+                        let (traced, consumed) = self.trace_elements(elm, loc, settings);
                         optimized.extend(traced);
                         tracing_consumed_locs.extend(consumed);
                     }
@@ -146,7 +178,15 @@ impl Optimizer {
             }
         }
         optimized.sort();
-        optimized.dedup();
+        // the duplicates are made from other cells
+        optimized.dedup_by(|removed, kept| {
+            if removed == kept {
+                *kept = kept.merge_cells(removed.cells());
+                true
+            } else {
+                false
+            }
+        });
         let arranged = self.arrange_elements(optimized);
         let grouped = self.group_elements(arranged);
         grouped
@@ -164,10 +204,10 @@ impl Optimizer {
         let mut circles = vec![];
         for elm in elements {
             match elm {
-                Element::Circle(_, _,) => {
+                Element::Circle(_, _, _) => {
                     circles.push(elm.clone());
                 }
-                Element::Line(_, _, ref stroke, ref start_feature, ref end_feature) => {
+                Element::Line(_, _, ref stroke, ref start_feature, ref end_feature, _) => {
                     if *start_feature == OpenCircle || *end_feature == OpenCircle
                         || *start_feature == BigOpenCircle || *end_feature == BigOpenCircle{
                             featured_circle_solid_lines.push(elm.clone())
@@ -183,7 +223,7 @@ impl Optimizer {
                         }
                     }
                 },
-                Element::Arc(_, _, _, _, _, ref stroke, _, _) => {
+                Element::Arc(_, _, _, _, _, ref stroke, _, _, _) => {
 
                     match *stroke {
                         Stroke::Solid => {
@@ -194,7 +234,7 @@ impl Optimizer {
                         }
                     }
                 },
-                Element::Text(_, _) => text.push(elm.clone()),
+                Element::Text(_, _, _) => text.push(elm.clone()),
             }
         }
         merged.extend(solid_lines);
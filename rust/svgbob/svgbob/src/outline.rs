@@ -0,0 +1,354 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use svg::node::element::Path as SvgPath;
+use ttf_parser::{Font, GlyphId, OutlineBuilder};
+use unicode_segmentation::UnicodeSegmentation;
+use element::round_to;
+use grid::xml_unescape;
+use loc::Loc;
+use loc_block::LocBlock;
+#[cfg(feature = "png")]
+use point::Point;
+use settings::Settings;
+
+/// CMU Typewriter Text, the monospace font of Foremark
+static BUNDLED: &[u8] = include_bytes!("../fonts/cmuntt.ttf");
+
+/// a TrueType or OpenType font the text is outlined with
+#[derive(Clone)]
+pub struct OutlineFont {
+    data: Arc<Cow<'static, [u8]>>,
+    /// the hash of the data, telling the fonts apart in the `Debug` output
+    hash: u64,
+}
+
+impl OutlineFont {
+    /// the bundled monospace font
+    pub fn bundled() -> OutlineFont {
+        OutlineFont::new(Cow::Borrowed(BUNDLED))
+    }
+
+    /// the font in `data`, none if it is not a font
+    pub fn from_data(data: Vec<u8>) -> Option<OutlineFont> {
+        if Font::from_data(&data, 0).is_none() {
+            return None;
+        }
+        Some(OutlineFont::new(Cow::Owned(data)))
+    }
+
+    fn new(data: Cow<'static, [u8]>) -> OutlineFont {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        OutlineFont {
+            data: Arc::new(data),
+            hash: hasher.finish(),
+        }
+    }
+
+    /// read the font in a TTF or OTF file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<OutlineFont> {
+        let data = fs::read(path)?;
+        OutlineFont::from_data(data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a TrueType or OpenType font"))
+    }
+
+    fn font(&self) -> Font<'_> {
+        Font::from_data(&self.data, 0).expect("checked when the font is loaded")
+    }
+
+    /// the outlines of the xml escaped `text` starting at `loc`,
+    /// each glyph is centered in the cells of its character
+    /// and sits on the same baseline as the `<text>` it replaces
+    pub(crate) fn outline_text(&self, loc: &Loc, text: &str, settings: &Settings) -> SvgPath {
+        let mut builder = PathBuilder {
+            d: String::new(),
+            pen: Pen::default(),
+            precision: if settings.compact { settings.precision } else { 2 },
+        };
+        self.outline(loc, &xml_unescape(text), settings, &mut builder);
+        SvgPath::new().set("d", builder.d.trim_end().to_string())
+    }
+
+    /// the contours of the glyphs of `text` placed as in `outline_text`,
+    /// with the curves flattened into lines
+    #[cfg(feature = "png")]
+    pub(crate) fn text_contours(&self, loc: &Loc, text: &str, settings: &Settings) -> Vec<Vec<Point>> {
+        let mut builder = ContourBuilder {
+            contours: vec![],
+            pen: Pen::default(),
+        };
+        self.outline(loc, text, settings, &mut builder);
+        builder.contours
+    }
+
+    /// draw the glyphs of `text` starting at `loc` with `builder`
+    fn outline<B: GlyphBuilder>(&self, loc: &Loc, text: &str, settings: &Settings, builder: &mut B) {
+        let font = self.font();
+        let mut block = LocBlock {
+            loc: loc.clone(),
+            settings: settings.clone(),
+        };
+        let scale = settings.font_size / font.units_per_em().unwrap_or(1000) as f32;
+        *builder.pen() = Pen {
+            x: 0.0,
+            y: block.y3(),
+            scale: scale,
+        };
+        for cluster in text.graphemes(true) {
+            let width = settings.cluster_width(cluster).unwrap_or(0) as i32;
+            if width == 0 {
+                continue;
+            }
+            let x0 = block.x0();
+            block.loc.x += width - 1;
+            let x4 = block.x4();
+            block.loc.x += 1;
+            // the marks the font has no glyph for are left out, the base character is always drawn
+            let glyphs: Vec<(GlyphId, f32)> = cluster
+                .chars()
+                .enumerate()
+                .filter_map(|(i, ch)| match font.glyph_index(ch) {
+                    Some(glyph) => Some(glyph),
+                    None if i == 0 => Some(GlyphId(0)),
+                    None => None,
+                })
+                .map(|glyph| (glyph, font.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale))
+                .collect();
+            // the base glyph is centered in the cells
+            let (base, base_advance) = glyphs[0];
+            let origin = (x0 + x4 - base_advance) / 2.0;
+            builder.pen().x = origin;
+            font.outline_glyph(base, builder);
+            for &(mark, advance) in &glyphs[1..] {
+                // a combining mark without advance is drawn from the pen position after the base glyph,
+                // one with an advance of its own, as in monospace fonts, from the origin of the base glyph
+                builder.pen().x = if advance == 0.0 {
+                    origin + base_advance
+                } else {
+                    origin + (base_advance - advance) / 2.0
+                };
+                font.outline_glyph(mark, builder);
+            }
+        }
+    }
+}
+
+/// shown with the size and the hash instead of the whole data,
+/// the id prefix of the svg is a hash of the settings and differs between fonts
+impl fmt::Debug for OutlineFont {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OutlineFont({} bytes, {:016x})", self.data.len(), self.hash)
+    }
+}
+
+/// where the glyph is drawn: its origin at `x`, `y` and the size of a font unit
+#[derive(Default)]
+struct Pen {
+    x: f32,
+    y: f32,
+    scale: f32,
+}
+
+impl Pen {
+    /// the font units are y up, the svg is y down
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.x + x * self.scale, self.y - y * self.scale)
+    }
+}
+
+/// an outline builder drawing the glyphs where its pen is
+trait GlyphBuilder: OutlineBuilder {
+    fn pen(&mut self) -> &mut Pen;
+}
+
+/// writes the outlines of the glyphs as svg path commands
+struct PathBuilder {
+    d: String,
+    pen: Pen,
+    /// the decimal places of the coordinates
+    precision: usize,
+}
+
+impl PathBuilder {
+    /// the svg coordinates rounded to `precision` decimal places,
+    /// 2 are plenty at the scale of a diagram
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = self.pen.point(x, y);
+        let precision = self.precision as i32;
+        (round_to(x, precision), round_to(y, precision))
+    }
+}
+
+impl GlyphBuilder for PathBuilder {
+    fn pen(&mut self) -> &mut Pen {
+        &mut self.pen
+    }
+}
+
+impl OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        write!(self.d, "M {} {} ", x, y).unwrap();
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        write!(self.d, "L {} {} ", x, y).unwrap();
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (x1, y1) = self.point(x1, y1);
+        let (x, y) = self.point(x, y);
+        write!(self.d, "Q {} {} {} {} ", x1, y1, x, y).unwrap();
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x1, y1) = self.point(x1, y1);
+        let (x2, y2) = self.point(x2, y2);
+        let (x, y) = self.point(x, y);
+        write!(self.d, "C {} {} {} {} {} {} ", x1, y1, x2, y2, x, y).unwrap();
+    }
+
+    fn close(&mut self) {
+        self.d.push_str("Z ");
+    }
+}
+
+/// the number of lines a curve of a glyph is flattened into
+#[cfg(feature = "png")]
+const CURVE_STEPS: usize = 8;
+
+/// collects the contours of the glyphs as polygons
+#[cfg(feature = "png")]
+struct ContourBuilder {
+    contours: Vec<Vec<Point>>,
+    pen: Pen,
+}
+
+#[cfg(feature = "png")]
+impl ContourBuilder {
+    fn push(&mut self, (x, y): (f32, f32)) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push(Point::new(x, y));
+        }
+    }
+
+    /// the point the current contour ends at
+    fn last(&self) -> (f32, f32) {
+        self.contours
+            .last()
+            .and_then(|contour| contour.last())
+            .map_or((0.0, 0.0), |p| (p.x, p.y))
+    }
+}
+
+#[cfg(feature = "png")]
+impl GlyphBuilder for ContourBuilder {
+    fn pen(&mut self) -> &mut Pen {
+        &mut self.pen
+    }
+}
+
+#[cfg(feature = "png")]
+impl OutlineBuilder for ContourBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.pen.point(x, y);
+        self.contours.push(vec![Point::new(x, y)]);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.pen.point(x, y);
+        self.push(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.last(), self.pen.point(x1, y1), self.pen.point(x, y));
+        for i in 1..=CURVE_STEPS {
+            let t = i as f32 / CURVE_STEPS as f32;
+            let bezier = |a: f32, b: f32, c: f32| (1.0 - t) * (1.0 - t) * a + 2.0 * (1.0 - t) * t * b + t * t * c;
+            self.push((bezier(p0.0, p1.0, p2.0), bezier(p0.1, p1.1, p2.1)));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (p0, p1, p2, p3) = (self.last(), self.pen.point(x1, y1), self.pen.point(x2, y2), self.pen.point(x, y));
+        for i in 1..=CURVE_STEPS {
+            let t = i as f32 / CURVE_STEPS as f32;
+            let u = 1.0 - t;
+            let bezier = |a: f32, b: f32, c: f32, d: f32| {
+                u * u * u * a + 3.0 * u * u * t * b + 3.0 * u * t * t * c + t * t * t * d
+            };
+            self.push((bezier(p0.0, p1.0, p2.0, p3.0), bezier(p0.1, p1.1, p2.1, p3.1)));
+        }
+    }
+
+    /// the polygons are closed when they are filled
+    fn close(&mut self) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use grid::Grid;
+
+    #[test]
+    fn test_outline_text() {
+        let settings = Settings::default();
+        let font = OutlineFont::bundled();
+        let path = font.outline_text(&Loc::new(2, 1), "a&lt;", &settings).to_string();
+        assert!(path.starts_with(r#"<path d="M "#));
+        // both glyphs are within their cells
+        let coords: Vec<f32> = path
+            .split(|c: char| c == '"' || c == ' ')
+            .filter_map(|v| v.parse().ok())
+            .collect();
+        let xs = coords.iter().step_by(2);
+        assert!(xs.clone().all(|&x| x >= 16.0 && x <= 32.0));
+        assert!(xs.clone().any(|&x| x > 24.0));
+    }
+
+    #[test]
+    fn test_invalid_font() {
+        assert!(OutlineFont::from_data(b"not a font".to_vec()).is_none());
+    }
+
+    #[test]
+    fn test_debug_tells_fonts_apart() {
+        let bundled = OutlineFont::bundled();
+        assert_eq!(format!("{:?}", bundled), format!("{:?}", OutlineFont::bundled()));
+        // the same size with a byte changed past the tables the font is checked with
+        let mut data = BUNDLED.to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let changed = OutlineFont::from_data(data).unwrap();
+        assert_ne!(format!("{:?}", bundled), format!("{:?}", changed));
+    }
+
+    #[test]
+    fn test_combining_mark() {
+        let settings = Settings::default();
+        let font = OutlineFont::bundled();
+        // the bundled font has no combining marks, the base glyph is drawn where it is without them
+        let path = |text: &str| font.outline_text(&Loc::new(2, 1), text, &settings).to_string();
+        assert_eq!(path("e"), path("e\u{301}"));
+    }
+
+    #[test]
+    fn test_outlined_svg() {
+        let mut settings = Settings::default();
+        settings.text_outlines = Some(OutlineFont::bundled());
+        let svg = Grid::from_str("hi->", &settings).get_svg().to_string();
+        assert!(!svg.contains("<text"));
+        // the glyphs of the text are filled in the foreground color
+        let glyphs = OutlineFont::bundled().outline_text(&Loc::new(0, 0), "hi", &settings).to_string();
+        let d = &glyphs[glyphs.find(" d=").unwrap()..];
+        assert!(svg.contains(&format!("<path class=\"fg_fill\"{}", d)));
+    }
+}
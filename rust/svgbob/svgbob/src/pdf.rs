@@ -0,0 +1,290 @@
+use std::f32::consts::PI;
+use std::fmt::Write;
+use unicode_segmentation::UnicodeSegmentation;
+use color::{parse_color, Rgba};
+use element::{num, ArcSegment, Cells, Feature, Shape, Stroke};
+use grid::{Grid, ViewBox};
+use loc::Loc;
+use point::Point;
+use renderer::{MarkerEnd, Renderer};
+use settings::Settings;
+
+/// the width of the glyphs of Courier in em
+const COURIER_WIDTH: f32 = 0.6;
+
+impl Grid {
+    /// get the diagram as a single page pdf of the size of the view box,
+    /// one svg unit is one point.
+    ///
+    /// The text is set in Courier, one of the standard fonts of pdf readers,
+    /// the characters outside of Latin-1 are replaced with `?`
+    pub fn get_pdf(&self) -> Vec<u8> {
+        self.render(PdfRenderer::new())
+    }
+}
+
+/// the pdf writer, drawing in svg coordinates with the y axis pointing down
+struct PdfRenderer {
+    /// the content stream of the page
+    ops: String,
+    settings: Settings,
+    /// the size of the page
+    size: (f32, f32),
+    /// the fill color of the marker shapes that are open
+    background: (f32, f32, f32),
+    foreground: (f32, f32, f32),
+    /// the stroke of the dash pattern that is set
+    dash: Stroke,
+}
+
+impl PdfRenderer {
+    fn new() -> PdfRenderer {
+        PdfRenderer {
+            ops: String::new(),
+            settings: Settings::default(),
+            size: (0.0, 0.0),
+            background: (1.0, 1.0, 1.0),
+            foreground: (0.0, 0.0, 0.0),
+            dash: Stroke::Solid,
+        }
+    }
+
+    fn op(&mut self, args: std::fmt::Arguments) {
+        self.ops.write_fmt(args).expect("writing to a string");
+        self.ops.push('\n');
+    }
+
+    fn dash(&mut self, stroke: &Stroke) {
+        if self.dash == *stroke {
+            return;
+        }
+        self.dash = stroke.clone();
+        match *stroke {
+            Stroke::Solid => self.op(format_args!("[] 0 d")),
+            // `stroke-dasharray: 5` of the stylesheet
+            Stroke::Dashed => self.op(format_args!("[5] 0 d")),
+        }
+    }
+
+    /// cubic bezier curves along the circle around `center` from the angle `start`,
+    /// each spanning at most a quarter of the circle
+    fn curve(&mut self, center: &Point, r: f32, start: f32, delta: f32) {
+        let count = (delta.abs() / (PI / 2.0)).ceil().max(1.0) as usize;
+        let step = delta / count as f32;
+        // the distance of the control points along the tangents
+        let k = 4.0 / 3.0 * (step / 4.0).tan() * r;
+        for i in 0..count {
+            let a0 = start + step * i as f32;
+            let a1 = a0 + step;
+            let (x0, y0) = (center.x + r * a0.cos(), center.y + r * a0.sin());
+            let (x1, y1) = (center.x + r * a1.cos(), center.y + r * a1.sin());
+            self.op(format_args!(
+                "{} {} {} {} {} {} c",
+                num(x0 - k * a0.sin()), num(y0 + k * a0.cos()),
+                num(x1 + k * a1.sin()), num(y1 - k * a1.cos()),
+                num(x1), num(y1)
+            ));
+        }
+    }
+
+    fn shape(&mut self, shape: &Shape) {
+        self.dash(&Stroke::Solid);
+        let open = match *shape {
+            Shape::Polygon(_, open) | Shape::Circle(_, _, open) => open,
+        };
+        if open {
+            let (r, g, b) = self.background;
+            self.op(format_args!("{} {} {} rg", r, g, b));
+        }
+        match *shape {
+            Shape::Polygon(ref points, _) => {
+                for (i, p) in points.iter().enumerate() {
+                    let op = if i == 0 { "m" } else { "l" };
+                    self.op(format_args!("{} {} {}", num(p.x), num(p.y), op));
+                }
+            }
+            Shape::Circle(ref c, r, _) => {
+                self.op(format_args!("{} {} m", num(c.x + r), num(c.y)));
+                self.curve(c, r, 0.0, 2.0 * PI);
+            }
+        }
+        if open {
+            // filled with the background and outlined
+            let (r, g, b) = self.foreground;
+            self.op(format_args!("h B {} {} {} rg", r, g, b));
+        } else {
+            self.op(format_args!("h f"));
+        }
+    }
+}
+
+impl Renderer for PdfRenderer {
+    type Output = Vec<u8>;
+
+    /// the content starts with the backdrop and the graphics state of the stylesheet
+    fn begin_document(&mut self, view_box: &ViewBox, settings: &Settings) {
+        self.settings = settings.clone();
+        self.size = (view_box.width, view_box.height);
+        // the page is white, the translucent colors are mixed with what they are drawn over
+        let page = Rgba::opaque(1.0, 1.0, 1.0);
+        let background = settings.background_color.as_ref().and_then(|color| parse_color(color));
+        let backdrop = background.map_or(page, |color| color.over(&page));
+        let foreground = parse_color(&settings.stroke_color).unwrap_or(Rgba::opaque(0.0, 0.0, 0.0));
+        self.background = rgb(&backdrop);
+        self.foreground = rgb(&foreground.over(&backdrop));
+        self.op(format_args!("1 0 0 -1 {} {} cm", num(-view_box.x), num(view_box.height + view_box.y)));
+        if background.map_or(false, |color| color.alpha > 0.0) {
+            let (r, g, b) = self.background;
+            self.op(format_args!(
+                "{} {} {} rg {} {} {} {} re f",
+                r, g, b, num(view_box.x), num(view_box.y), num(view_box.width), num(view_box.height)
+            ));
+        }
+        let (r, g, b) = self.foreground;
+        self.op(format_args!("{} {} {} RG {} {} {} rg", r, g, b, r, g, b));
+        self.op(format_args!("{} w 1 J 0 j", num(settings.stroke_width)));
+    }
+
+    fn line(&mut self, start: &Point, end: &Point, stroke: &Stroke, _cells: &Cells) {
+        self.dash(stroke);
+        self.op(format_args!("{} {} m {} {} l S", num(start.x), num(start.y), num(end.x), num(end.y)));
+    }
+
+    fn arc(&mut self, arc: &ArcSegment, stroke: &Stroke, _cells: &Cells) {
+        self.dash(stroke);
+        let geometry = arc.geometry();
+        self.op(format_args!("{} {} m", num(arc.start.x), num(arc.start.y)));
+        self.curve(&geometry.center, geometry.radius, geometry.start_angle, geometry.sweep_angle);
+        self.op(format_args!("S"));
+    }
+
+    fn circle(&mut self, center: &Point, radius: f32, _cells: &Cells) {
+        self.dash(&Stroke::Solid);
+        self.op(format_args!("{} {} m", num(center.x + radius), num(center.y)));
+        self.curve(center, radius, 0.0, 2.0 * PI);
+        self.op(format_args!("h S"));
+    }
+
+    fn text(&mut self, loc: &Loc, text: &str, _cells: &Cells) {
+        let x = loc.x as f32 * self.settings.text_width;
+        let y = loc.y as f32 * self.settings.text_height + self.settings.text_height * 3.0 / 4.0;
+        // each character is stretched or squeezed to the width of a cell
+        let scaling = 100.0 * self.settings.text_width / (COURIER_WIDTH * self.settings.font_size);
+        let string = pdf_string(text, &self.settings);
+        let font_size = self.settings.font_size;
+        self.op(format_args!(
+            "BT /F1 {} Tf {} Tz 1 0 0 -1 {} {} Tm {} Tj ET",
+            num(font_size), num(scaling), num(x), num(y), string
+        ));
+    }
+
+    fn marker(&mut self, feature: &Feature, _end: MarkerEnd, at: &Point, direction: (f32, f32)) {
+        if let Some(shape) = feature.shape(at, direction, &self.settings) {
+            self.shape(&shape);
+        }
+    }
+
+    fn end_document(self) -> Vec<u8> {
+        write_document(self.size.0, self.size.1, &self.ops)
+    }
+}
+
+/// the text as a pdf string in the WinAnsi encoding of the font,
+/// each character takes up as many cells as it does in the grid
+fn pdf_string(text: &str, settings: &Settings) -> String {
+    let mut string = String::from("(");
+    for cluster in text.graphemes(true) {
+        let width = settings.cluster_width(cluster).unwrap_or(0);
+        if width == 0 {
+            continue;
+        }
+        match cluster.chars().next() {
+            Some(ch) if ch == '(' || ch == ')' || ch == '\\' => {
+                string.push('\\');
+                string.push(ch);
+            }
+            Some(ch) if ch >= ' ' && ch <= '~' => string.push(ch),
+            // Latin-1 is the same in WinAnsi, written as octal to keep the pdf ascii
+            Some(ch) if ch >= '\u{a0}' && ch <= '\u{ff}' => {
+                write!(string, "\\{:03o}", ch as u32).expect("writing to a string")
+            }
+            _ => string.push('?'),
+        }
+        for _ in 1..width {
+            string.push(' ');
+        }
+    }
+    string.push(')');
+    string
+}
+
+/// the rgb components of an opaque color, rounded to 3 decimals
+fn rgb(color: &Rgba) -> (f32, f32, f32) {
+    let round = |v: f32| (v * 1000.0).round() / 1000.0;
+    (round(color.red), round(color.green), round(color.blue))
+}
+
+/// the pdf file of a single page with the content stream `content`
+fn write_document(width: f32, height: f32, content: &str) -> Vec<u8> {
+    let objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+             /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>",
+            num(width), num(height)
+        ),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_string(),
+    ];
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        writeln!(pdf, "{} 0 obj\n{}\nendobj", i + 1, object).expect("writing to a string");
+    }
+    let xref = pdf.len();
+    writeln!(pdf, "xref\n0 {}\n0000000000 65535 f ", objects.len() + 1).expect("writing to a string");
+    for offset in offsets {
+        writeln!(pdf, "{:010} 00000 n ", offset).expect("writing to a string");
+    }
+    writeln!(
+        pdf,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF",
+        objects.len() + 1,
+        xref
+    ).expect("writing to a string");
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pdf() {
+        let grid = Grid::from_str("+--+\n|  |--> \"(a)\"\n+--+", &Settings::default());
+        let pdf = String::from_utf8(grid.get_pdf()).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/MediaBox [0 0 104 48]"));
+        assert!(pdf.contains("(\\(a\\)) Tj"));
+        // the cross reference table is where `startxref` points to
+        let start: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert!(pdf[start..].starts_with("xref\n0 6\n"));
+    }
+
+    #[test]
+    fn test_pdf_colors() {
+        let mut settings = Settings::default();
+        settings.stroke_color = "navy".to_string();
+        settings.background_color = Some("rgba(255,0,0,0.5)".to_string());
+        let pdf = String::from_utf8(Grid::from_str("-->", &settings).get_pdf()).unwrap();
+        // the translucent background is mixed with the white page
+        assert!(pdf.contains("1 0.5 0.5 rg 0 0 24 16 re f\n"));
+        assert!(pdf.contains("0 0 0.502 RG 0 0 0.502 rg\n"));
+
+        settings.background_color = Some("transparent".to_string());
+        let pdf = String::from_utf8(Grid::from_str("-->", &settings).get_pdf()).unwrap();
+        assert!(!pdf.contains(" re f"));
+    }
+}
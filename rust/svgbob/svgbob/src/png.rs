@@ -0,0 +1,469 @@
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use png_encoder;
+use color::{parse_color, Rgba};
+use element::{ArcSegment, Cells, Feature, Shape, Stroke};
+use grid::{Grid, ViewBox};
+use loc::Loc;
+use outline::OutlineFont;
+use point::Point;
+use renderer::{MarkerEnd, Renderer};
+use settings::Settings;
+
+/// the resolution of the png at a scale of 1, one pixel per svg unit
+pub const DEFAULT_DPI: f32 = 96.0;
+
+/// the most pixels a png can have, 4096 x 4096
+const MAX_PIXELS: f32 = 16_777_216.0;
+
+/// the samples per pixel side the polygons are filled with
+const SAMPLES: usize = 4;
+
+/// the length of the dashes and of the gaps between them, `stroke-dasharray: 5` of the stylesheet
+const DASH: f32 = 5.0;
+
+/// the reason the diagram could not be rasterized
+#[derive(Debug)]
+pub enum PngError {
+    /// the png would have no pixels or is too large
+    Size(f32, f32),
+    /// the pixels could not be encoded as png
+    Encode(String),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PngError::Size(width, height) => write!(f, "invalid png size {}x{}", width, height),
+            PngError::Encode(ref e) => write!(f, "failed to encode png: {}", e),
+        }
+    }
+}
+
+impl Error for PngError {}
+
+impl Grid {
+    /// get the diagram rasterized as png, `scale` is the number of pixels per svg unit, `dpi / DEFAULT_DPI`.
+    ///
+    /// The text is drawn with the outlines of the `text_outlines` font of the settings
+    /// or else the bundled font, so the png is the same whatever fonts are installed
+    pub fn get_png(&self, scale: f32) -> Result<Vec<u8>, PngError> {
+        self.render(PngRenderer::new(scale))
+    }
+}
+
+/// the rasterizer, drawing in svg coordinates with the y axis pointing down
+struct PngRenderer {
+    scale: f32,
+    settings: Settings,
+    /// the svg point at the top left corner of the png
+    origin: Point,
+    canvas: Option<Canvas>,
+    error: Option<PngError>,
+    font: OutlineFont,
+    /// the fill of the marker shapes that are open, none when there is no background
+    background: Option<Rgba>,
+    foreground: Rgba,
+}
+
+impl PngRenderer {
+    fn new(scale: f32) -> PngRenderer {
+        PngRenderer {
+            scale: scale,
+            settings: Settings::default(),
+            origin: Point::new(0.0, 0.0),
+            canvas: None,
+            error: None,
+            font: OutlineFont::bundled(),
+            background: None,
+            foreground: Rgba::opaque(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// the svg point `p` in pixels
+    fn pixel(&self, p: &Point) -> Point {
+        Point::new((p.x - self.origin.x) * self.scale, (p.y - self.origin.y) * self.scale)
+    }
+
+    /// stroke the svg `points` joined by lines
+    fn polyline(&mut self, points: &[Point], stroke: &Stroke) {
+        let points: Vec<Point> = points.iter().map(|p| self.pixel(p)).collect();
+        let width = self.settings.stroke_width * self.scale;
+        let segments = match *stroke {
+            Stroke::Solid => points.windows(2).map(|w| (w[0].clone(), w[1].clone())).collect(),
+            Stroke::Dashed => dashes(&points, DASH * self.scale),
+        };
+        let color = self.foreground;
+        if let Some(ref mut canvas) = self.canvas {
+            canvas.stroke(&segments, width, &color);
+        }
+    }
+
+    /// fill the svg `contours` with the nonzero rule
+    fn fill(&mut self, contours: &[Vec<Point>], color: &Rgba) {
+        let contours: Vec<Vec<Point>> = contours
+            .iter()
+            .map(|contour| contour.iter().map(|p| self.pixel(p)).collect())
+            .collect();
+        if let Some(ref mut canvas) = self.canvas {
+            canvas.fill(&contours, color);
+        }
+    }
+
+    fn shape(&mut self, shape: &Shape) {
+        let (outline, open) = match *shape {
+            Shape::Polygon(ref points, open) => (points.clone(), open),
+            Shape::Circle(ref c, r, open) => (circle_points(c, r, 0.0, 2.0 * PI), open),
+        };
+        if !open {
+            let color = self.foreground;
+            self.fill(&[outline], &color);
+            return;
+        }
+        // filled with the background and outlined
+        if let Some(background) = self.background {
+            self.fill(&[outline.clone()], &background);
+        }
+        let mut closed = outline.clone();
+        closed.extend(outline.first().cloned());
+        self.polyline(&closed, &Stroke::Solid);
+    }
+}
+
+impl Renderer for PngRenderer {
+    type Output = Result<Vec<u8>, PngError>;
+
+    /// the png is transparent unless the settings have a background
+    fn begin_document(&mut self, view_box: &ViewBox, settings: &Settings) {
+        self.settings = settings.clone();
+        self.origin = Point::new(view_box.x, view_box.y);
+        if let Some(ref font) = settings.text_outlines {
+            self.font = font.clone();
+        }
+        let (width, height) = (view_box.width * self.scale, view_box.height * self.scale);
+        if !(width >= 1.0 && height >= 1.0 && width.ceil() * height.ceil() <= MAX_PIXELS) {
+            self.error = Some(PngError::Size(width, height));
+            return;
+        }
+        let mut canvas = Canvas::new(width.ceil() as usize, height.ceil() as usize);
+        self.background = settings.background_color.as_ref().and_then(|color| parse_color(color));
+        if let Some(background) = self.background {
+            canvas.clear(&background);
+        }
+        self.foreground = parse_color(&settings.stroke_color).unwrap_or(Rgba::opaque(0.0, 0.0, 0.0));
+        self.canvas = Some(canvas);
+    }
+
+    fn line(&mut self, start: &Point, end: &Point, stroke: &Stroke, _cells: &Cells) {
+        self.polyline(&[start.clone(), end.clone()], stroke);
+    }
+
+    fn arc(&mut self, arc: &ArcSegment, stroke: &Stroke, _cells: &Cells) {
+        let geometry = arc.geometry();
+        let points = circle_points(&geometry.center, geometry.radius, geometry.start_angle, geometry.sweep_angle);
+        self.polyline(&points, stroke);
+    }
+
+    fn circle(&mut self, center: &Point, radius: f32, _cells: &Cells) {
+        let points = circle_points(center, radius, 0.0, 2.0 * PI);
+        self.polyline(&points, &Stroke::Solid);
+    }
+
+    fn text(&mut self, loc: &Loc, text: &str, _cells: &Cells) {
+        let contours = self.font.text_contours(loc, text, &self.settings);
+        let color = self.foreground;
+        self.fill(&contours, &color);
+    }
+
+    fn marker(&mut self, feature: &Feature, _end: MarkerEnd, at: &Point, direction: (f32, f32)) {
+        if let Some(shape) = feature.shape(at, direction, &self.settings) {
+            self.shape(&shape);
+        }
+    }
+
+    fn end_document(self) -> Result<Vec<u8>, PngError> {
+        match (self.error, self.canvas) {
+            (Some(error), _) => Err(error),
+            (None, Some(canvas)) => canvas.encode(),
+            (None, None) => Err(PngError::Size(0.0, 0.0)),
+        }
+    }
+}
+
+/// the points of the circle around `center` from the angle `start` through `sweep`,
+/// close enough for the lines between them to look round
+fn circle_points(center: &Point, radius: f32, start: f32, sweep: f32) -> Vec<Point> {
+    let count = (sweep.abs() / (PI / 32.0)).ceil().max(1.0) as usize;
+    (0..=count)
+        .map(|i| {
+            let angle = start + sweep * i as f32 / count as f32;
+            Point::new(center.x + radius * angle.cos(), center.y + radius * angle.sin())
+        })
+        .collect()
+}
+
+/// the dashes of `dash` length along the polyline `points`, separated by gaps of the same length
+fn dashes(points: &[Point], dash: f32) -> Vec<(Point, Point)> {
+    let mut segments = vec![];
+    // the distance along the polyline modulo a dash and a gap
+    let mut phase = 0.0;
+    for w in points.windows(2) {
+        let (a, b) = (&w[0], &w[1]);
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        let at = |d: f32| Point::new(a.x + (b.x - a.x) * d / length, a.y + (b.y - a.y) * d / length);
+        let mut d = 0.0;
+        while d < length {
+            let step = if phase < dash { dash - phase } else { 2.0 * dash - phase };
+            let next = (d + step).min(length);
+            if phase < dash {
+                segments.push((at(d), at(next)));
+            }
+            phase = (phase + next - d) % (2.0 * dash);
+            d = next;
+        }
+    }
+    segments
+}
+
+/// the pixels with their colors premultiplied by the alpha
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width: width,
+            height: height,
+            pixels: vec![[0.0; 4]; width * height],
+        }
+    }
+
+    fn clear(&mut self, color: &Rgba) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.blend(x, y, color, 1.0);
+            }
+        }
+    }
+
+    /// draw `color` over the pixel at `x`, `y`, covering `coverage` of it
+    fn blend(&mut self, x: usize, y: usize, color: &Rgba, coverage: f32) {
+        let alpha = color.alpha * coverage.min(1.0);
+        let pixel = &mut self.pixels[y * self.width + x];
+        let source = [color.red * alpha, color.green * alpha, color.blue * alpha, alpha];
+        for (c, s) in pixel.iter_mut().zip(source.iter()) {
+            *c = s + *c * (1.0 - alpha);
+        }
+    }
+
+    /// the rows and columns of pixels touching the box from `min` to `max`, clipped to the canvas
+    fn clip(&self, min: (f32, f32), max: (f32, f32)) -> (usize, usize, usize, usize) {
+        let clamp = |v: f32, size: usize| v.max(0.0).min(size as f32) as usize;
+        (
+            clamp(min.0.floor(), self.width),
+            clamp(min.1.floor(), self.height),
+            clamp(max.0.ceil(), self.width),
+            clamp(max.1.ceil(), self.height),
+        )
+    }
+
+    /// stroke the `segments` with round caps, each pixel is covered by how far it is inside the stroke.
+    /// The segments of a polyline overlap at their joints, the pixels there are only drawn once
+    fn stroke(&mut self, segments: &[(Point, Point)], width: f32, color: &Rgba) {
+        let half = width / 2.0;
+        let bounds = |a: &Point, b: &Point| {
+            let min = (a.x.min(b.x) - half - 1.0, a.y.min(b.y) - half - 1.0);
+            let max = (a.x.max(b.x) + half + 1.0, a.y.max(b.y) + half + 1.0);
+            (min, max)
+        };
+        let (min, max) = segments.iter().fold(
+            ((std::f32::MAX, std::f32::MAX), (std::f32::MIN, std::f32::MIN)),
+            |(min, max), &(ref a, ref b)| {
+                let (smin, smax) = bounds(a, b);
+                ((min.0.min(smin.0), min.1.min(smin.1)), (max.0.max(smax.0), max.1.max(smax.1)))
+            },
+        );
+        let (x0, y0, x1, y1) = self.clip(min, max);
+        let columns = x1.saturating_sub(x0);
+        let mut coverage = vec![0.0f32; columns * y1.saturating_sub(y0)];
+        for &(ref a, ref b) in segments {
+            let (min, max) = bounds(a, b);
+            let (sx0, sy0, sx1, sy1) = self.clip(min, max);
+            for y in sy0..sy1 {
+                for x in sx0..sx1 {
+                    let p = Point::new(x as f32 + 0.5, y as f32 + 0.5);
+                    let covered = &mut coverage[(y - y0) * columns + x - x0];
+                    *covered = covered.max(half + 0.5 - segment_distance(&p, a, b));
+                }
+            }
+        }
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let covered = coverage[(y - y0) * columns + x - x0];
+                if covered > 0.0 {
+                    self.blend(x, y, color, covered);
+                }
+            }
+        }
+    }
+
+    /// fill the `contours` with the nonzero rule, sampling each pixel `SAMPLES` x `SAMPLES` times
+    fn fill(&mut self, contours: &[Vec<Point>], color: &Rgba) {
+        let mut edges = vec![];
+        for contour in contours {
+            for (i, a) in contour.iter().enumerate() {
+                let b = &contour[(i + 1) % contour.len()];
+                if a.y != b.y {
+                    edges.push((a.clone(), b.clone()));
+                }
+            }
+        }
+        if edges.is_empty() {
+            return;
+        }
+        let min = edges.iter().fold((std::f32::MAX, std::f32::MAX), |(x, y), &(ref a, ref b)| {
+            (x.min(a.x).min(b.x), y.min(a.y).min(b.y))
+        });
+        let max = edges.iter().fold((std::f32::MIN, std::f32::MIN), |(x, y), &(ref a, ref b)| {
+            (x.max(a.x).max(b.x), y.max(a.y).max(b.y))
+        });
+        let (x0, y0, x1, y1) = self.clip(min, max);
+        let step = 1.0 / SAMPLES as f32;
+        let mut coverage = vec![0usize; x1 - x0];
+        for y in y0..y1 {
+            for c in coverage.iter_mut() {
+                *c = 0;
+            }
+            for sample in 0..SAMPLES {
+                let sy = y as f32 + (sample as f32 + 0.5) * step;
+                // where the edges cross the sample row and which way they go
+                let mut crossings: Vec<(f32, i32)> = edges
+                    .iter()
+                    .filter(|&&(ref a, ref b)| (a.y <= sy) != (b.y <= sy))
+                    .map(|&(ref a, ref b)| {
+                        let x = a.x + (sy - a.y) * (b.x - a.x) / (b.y - a.y);
+                        (x, if b.y > a.y { 1 } else { -1 })
+                    })
+                    .collect();
+                crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding == 0 {
+                        continue;
+                    }
+                    let (start, end) = (pair[0].0, pair[1].0);
+                    let (sx0, _, sx1, _) = self.clip((start, 0.0), (end, 0.0));
+                    for x in sx0.max(x0)..sx1.min(x1) {
+                        for column in 0..SAMPLES {
+                            let sx = x as f32 + (column as f32 + 0.5) * step;
+                            if sx >= start && sx < end {
+                                coverage[x - x0] += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            for x in x0..x1 {
+                if coverage[x - x0] > 0 {
+                    self.blend(x, y, color, coverage[x - x0] as f32 / (SAMPLES * SAMPLES) as f32);
+                }
+            }
+        }
+    }
+
+    /// the pixels as an 8 bit rgba png
+    fn encode(&self) -> Result<Vec<u8>, PngError> {
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            let alpha = pixel[3];
+            for c in &pixel[..3] {
+                let straight = if alpha > 0.0 { c / alpha } else { 0.0 };
+                data.push((straight.max(0.0).min(1.0) * 255.0).round() as u8);
+            }
+            data.push((alpha.max(0.0).min(1.0) * 255.0).round() as u8);
+        }
+        let mut png = vec![];
+        {
+            let mut encoder = png_encoder::Encoder::new(&mut png, self.width as u32, self.height as u32);
+            encoder.set_color(png_encoder::ColorType::RGBA);
+            encoder.set_depth(png_encoder::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(|e| PngError::Encode(e.to_string()))?;
+            writer.write_image_data(&data).map_err(|e| PngError::Encode(e.to_string()))?;
+        }
+        Ok(png)
+    }
+}
+
+/// the distance of `p` to the segment from `a` to `b`
+fn segment_distance(p: &Point, a: &Point, b: &Point) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length).max(0.0).min(1.0)
+    };
+    ((a.x + t * dx - p.x).powi(2) + (a.y + t * dy - p.y).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the width, height and rgba pixels of the png
+    fn decode(png: &[u8]) -> (usize, usize, Vec<u8>) {
+        let (info, mut reader) = png_encoder::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        (info.width as usize, info.height as usize, pixels)
+    }
+
+    #[test]
+    fn test_png_size() {
+        let grid = Grid::from_str("+--+\n|  |\n+--+", &Settings::default());
+        let png = grid.get_png(2.0).unwrap();
+        assert_eq!(b"\x89PNG", &png[..4]);
+        // the width and height in the IHDR chunk
+        assert_eq!([0, 0, 0, 64, 0, 0, 0, 96], png[16..24]);
+    }
+
+    #[test]
+    fn test_png_pixels() {
+        let mut settings = Settings::default();
+        settings.background_color = Some("white".to_string());
+        settings.stroke_color = "red".to_string();
+        let (width, height, pixels) = decode(&Grid::from_str("--- A", &settings).get_png(1.0).unwrap());
+        assert_eq!((40, 16), (width, height));
+        let pixel = |x: usize, y: usize| &pixels[(y * width + x) * 4..(y * width + x) * 4 + 4];
+        // the line in the middle of the row, the background around it
+        assert_eq!([255, 0, 0, 255], pixel(12, 8));
+        assert_eq!([255, 255, 255, 255], pixel(12, 2));
+        // the text is drawn with the bundled font, its strokes are antialiased at this size
+        let text = (32..40).flat_map(|x| (0..16).map(move |y| (x, y)));
+        assert!(text.clone().any(|(x, y)| pixel(x, y)[0] == 255 && pixel(x, y)[1] < 64));
+        assert!(text.clone().any(|(x, y)| pixel(x, y) == [255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn test_png_transparent() {
+        let mut settings = Settings::default();
+        settings.background_color = None;
+        let (width, _, pixels) = decode(&Grid::from_str("---", &settings).get_png(1.0).unwrap());
+        assert_eq!(0, pixels[(2 * width + 12) * 4 + 3]);
+        assert_eq!([0, 0, 0, 255], pixels[(8 * width + 12) * 4..(8 * width + 12) * 4 + 4]);
+    }
+
+    #[test]
+    fn test_png_too_large() {
+        let grid = Grid::from_str("---", &Settings::default());
+        match grid.get_png(10_000.0) {
+            Err(PngError::Size(_, _)) => (),
+            other => panic!("expected a size error, got {:?}", other.map(|png| png.len())),
+        }
+    }
+}
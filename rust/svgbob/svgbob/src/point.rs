@@ -1,7 +1,11 @@
 
 use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, PartialOrd, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Point {
     pub x: f32,
     pub y: f32,
@@ -17,6 +21,15 @@ impl Ord for Point{
 impl Eq for Point{
 }
 
+/// hashed by the bits of the coordinates,
+/// `-0.0` is hashed the same as `0.0` since they are equal
+impl Hash for Point {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.x + 0.0).to_bits().hash(state);
+        (self.y + 0.0).to_bits().hash(state);
+    }
+}
+
 impl Point {
     pub fn new(x: f32, y: f32) -> Point {
         Point { x: x, y: y }
@@ -0,0 +1,149 @@
+use element::{ArcSegment, Cells, Element, Feature, Stroke};
+use grid::{xml_unescape, Grid, ViewBox};
+use loc::Loc;
+use point::Point;
+use settings::Settings;
+
+/// the end of a line or arc a marker is drawn at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarkerEnd {
+    Start,
+    End,
+}
+
+/// A backend the diagram is drawn with, see `Grid::render`.
+///
+/// The optimized elements are fed to the renderer in groups of connected elements,
+/// all the coordinates are in svg units with the y axis pointing down.
+/// The markers of a line or arc are fed right after it.
+pub trait Renderer {
+    /// the drawn document
+    type Output;
+
+    /// start the document showing `view_box` of the grid,
+    /// `settings` are the settings of the grid including its directives
+    fn begin_document(&mut self, view_box: &ViewBox, settings: &Settings);
+
+    /// start a group of connected elements
+    fn begin_group(&mut self) {}
+
+    /// end the group started with `begin_group`
+    fn end_group(&mut self) {}
+
+    /// a straight line from `start` to `end`
+    fn line(&mut self, start: &Point, end: &Point, stroke: &Stroke, cells: &Cells);
+
+    /// an arc of the svg path command `A`
+    fn arc(&mut self, arc: &ArcSegment, stroke: &Stroke, cells: &Cells);
+
+    /// a circle outline
+    fn circle(&mut self, center: &Point, radius: f32, cells: &Cells);
+
+    /// text starting at the cell `loc`, each character takes up as many cells as it does in the grid
+    fn text(&mut self, loc: &Loc, text: &str, cells: &Cells);
+
+    /// the marker at `end` of the line or arc drawn last,
+    /// `direction` is the unit vector of the stroke at the point `at`
+    fn marker(&mut self, feature: &Feature, end: MarkerEnd, at: &Point, direction: (f32, f32));
+
+    /// finish the document
+    fn end_document(self) -> Self::Output;
+}
+
+impl Grid {
+    /// draw the diagram with `renderer`
+    pub fn render<R: Renderer>(&self, mut renderer: R) -> R::Output {
+        self.feed(self.get_all_elements(), &mut renderer);
+        renderer.end_document()
+    }
+
+    /// feed the optimized `elements` to the renderer, up to but not including `end_document`.
+    /// `elements` are the cell elements of each pass as in `get_all_elements`
+    pub(crate) fn feed<R: Renderer>(&self, elements: Vec<Vec<Vec<Element>>>, renderer: &mut R) {
+        let optimized_elements = self.optimize(elements);
+        let view_box = self.get_view_box(&optimized_elements);
+        renderer.begin_document(&view_box, &self.settings);
+        for group in optimized_elements {
+            renderer.begin_group();
+            for elm in group {
+                feed_element(&elm, renderer);
+            }
+            renderer.end_group();
+        }
+    }
+}
+
+fn feed_element<R: Renderer>(elm: &Element, renderer: &mut R) {
+    match *elm {
+        Element::Line(ref s, ref e, ref stroke, _, _, ref cells) => renderer.line(s, e, stroke, cells),
+        Element::Arc(ref s, ref e, radius, ref arc_flag, sweep, ref stroke, _, _, ref cells) => {
+            let arc = ArcSegment {
+                start: s.clone(),
+                end: e.clone(),
+                radius: radius,
+                arc_flag: arc_flag.clone(),
+                sweep: sweep,
+            };
+            renderer.arc(&arc, stroke, cells)
+        }
+        Element::Circle(ref c, r, ref cells) => renderer.circle(c, r, cells),
+        Element::Text(ref loc, ref text, ref cells) => renderer.text(loc, &xml_unescape(text), cells),
+    }
+    for (feature, end, at, direction) in elm.get_markers() {
+        renderer.marker(feature, end, at, direction);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// records the calls it gets
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Renderer for Recorder {
+        type Output = Vec<String>;
+
+        fn begin_document(&mut self, view_box: &ViewBox, _settings: &Settings) {
+            self.0.push(format!("begin {}", view_box));
+        }
+        fn begin_group(&mut self) {
+            self.0.push("group".to_string());
+        }
+        fn line(&mut self, start: &Point, end: &Point, _stroke: &Stroke, _cells: &Cells) {
+            self.0.push(format!("line {},{} {},{}", start.x, start.y, end.x, end.y));
+        }
+        fn arc(&mut self, _arc: &ArcSegment, _stroke: &Stroke, _cells: &Cells) {
+            self.0.push("arc".to_string());
+        }
+        fn circle(&mut self, _center: &Point, _radius: f32, _cells: &Cells) {
+            self.0.push("circle".to_string());
+        }
+        fn text(&mut self, loc: &Loc, text: &str, _cells: &Cells) {
+            self.0.push(format!("text {},{} {}", loc.x, loc.y, text));
+        }
+        fn marker(&mut self, feature: &Feature, end: MarkerEnd, _at: &Point, direction: (f32, f32)) {
+            self.0.push(format!("marker {:?} {:?} {:?}", feature, end, direction));
+        }
+        fn end_document(self) -> Vec<String> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let calls = Grid::from_str("--> \"a<b\"", &Settings::default()).render(Recorder::default());
+        assert_eq!(
+            vec![
+                "begin 0 0 72 16",
+                "group",
+                "line 0,8 20,8",
+                "marker Arrow End (1.0, 0.0)",
+                "group",
+                "text 4,0 a<b",
+            ],
+            calls
+        );
+    }
+}
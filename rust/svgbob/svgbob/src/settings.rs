@@ -1,3 +1,13 @@
+use std::str::FromStr;
+use unicode_width::UnicodeWidthChar;
+use unicode_segmentation::UnicodeSegmentation;
+use element::round_to;
+#[cfg(feature = "text-outlines")]
+use outline::OutlineFont;
+
+/// the most decimal places the coordinates are rounded to,
+/// beyond this the f32 coordinates have no more precision to show
+pub(crate) const MAX_PRECISION: usize = 6;
 
 #[derive(Debug, Clone)]
 pub struct Settings {
@@ -15,11 +25,134 @@ pub struct Settings {
     pub stroke_width: f32,
     /// stroke color, default black
     pub stroke_color: String,
-    /// background color: default white
-    pub background_color: String
+    /// background color, none leaves out the backdrop
+    /// making the svg transparent (default: white)
+    pub background_color: Option<String>,
+    /// number of columns between tab stops, tabs are expanded
+    /// to spaces before the grid is laid out (default: 8)
+    /// setting this to 0 disables the expansion and the tabs are then ignored
+    /// like the other control characters, widths above 32 are clamped
+    pub tab_width: usize,
+    /// treat East Asian ambiguous-width characters such as `○`, `→`
+    /// and greek letters as 2 cells wide, the way CJK terminals display them
+    /// (default: false)
+    pub cjk_width: bool,
+    /// tag each svg element with the cells of the grid it is made from,
+    /// as `data-cells="x0,y0,x1,y1"` with both corners inclusive (default: false)
+    pub source_map: bool,
+    /// how the ids of the markers in `<defs>` are kept apart from the ids
+    /// of other svgs in the same html document (default: unprefixed)
+    pub id_namespace: IdNamespace,
+    /// draw the arrowheads and the dots at the end of the lines as polygons and circles
+    /// instead of svg markers, for the svg consumers that don't support markers
+    /// (default: false)
+    pub inline_markers: bool,
+    /// draw the connected lines and arcs of the same stroke as one `<path>`,
+    /// joining them at the corners instead of overlapping their round caps
+    /// (default: false)
+    pub merge_paths: bool,
+    /// draw the closed rectangles with the same rounding at every corner
+    /// as one `<rect>` instead of their sides and corners,
+    /// the lines attached to them are still drawn on their own (default: false)
+    pub rect_shapes: bool,
+    /// make the svg smaller: round the coordinates to `precision` decimal places,
+    /// minify the stylesheet and set the class shared by the elements of a group
+    /// on the `<g>` (default: false)
+    pub compact: bool,
+    /// the decimal places of the coordinates in compact mode,
+    /// the tikz coordinates, which are in cells, get one more (default: 2)
+    pub precision: usize,
+    /// how the size of the svg is specified (default: fixed)
+    pub sizing: Sizing,
+    /// crop the svg to the drawing with this much padding around it,
+    /// leaving out the blank rows and columns (default: None, the svg covers the whole grid)
+    pub crop: Option<f32>,
+    /// draw the text as `<path>` outlines of this font instead of `<text>`,
+    /// the svg then looks the same without any font installed (default: None)
+    #[cfg(feature = "text-outlines")]
+    pub text_outlines: Option<OutlineFont>,
+}
+
+/// how the svg is sized, the intrinsic size is always `Grid::get_size`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sizing {
+    /// `width` and `height` attributes of the intrinsic size
+    Fixed,
+    /// only a `viewBox`, the svg scales to the width of its container
+    ViewBox,
+    /// a `viewBox` and a `max-width` style of the intrinsic width,
+    /// the svg shrinks with its container but doesn't grow beyond its intrinsic size
+    ViewBoxMaxWidth,
+}
+
+/// parsed from `fixed`, `viewbox` or `max-width`
+impl FromStr for Sizing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Sizing, String> {
+        match s {
+            "fixed" => Ok(Sizing::Fixed),
+            "viewbox" => Ok(Sizing::ViewBox),
+            "max-width" => Ok(Sizing::ViewBoxMaxWidth),
+            _ => Err(format!("unknown sizing `{}`, expecting fixed, viewbox or max-width", s)),
+        }
+    }
+}
+
+/// the prefix of the ids defined in the svg
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdNamespace {
+    /// the ids are used as is: `triangle`, `circle`, ...
+    Unprefixed,
+    /// the ids are prefixed with this string: `diagram1-triangle`, ...
+    /// and the style sheet only applies to the svg, which gets the string as a class
+    Prefix(String),
+    /// the ids are prefixed with a hash of the diagram and its settings,
+    /// svgs of different diagrams get different ids
+    ContentHash,
+}
+
+impl IdNamespace {
+    /// the namespace of the ids prefixed with `prefix`,
+    /// the prefix is written as is into the ids and the style sheet of the svg
+    /// so it has to be a letter followed by letters, digits, `-` or `_`
+    pub fn prefix(prefix: &str) -> Result<IdNamespace, String> {
+        if is_valid_prefix(prefix) {
+            Ok(IdNamespace::Prefix(prefix.to_string()))
+        } else {
+            Err(format!(
+                "invalid id prefix `{}`, expecting a letter followed by letters, digits, `-` or `_`",
+                prefix
+            ))
+        }
+    }
+}
+
+/// a prefix usable in an xml id and a css class selector without escaping
+pub(crate) fn is_valid_prefix(prefix: &str) -> bool {
+    let mut chars = prefix.chars();
+    chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
 impl Settings {
+    /// the id of the `name` definition, prefixed according to `id_namespace`
+    pub fn id(&self, name: &str) -> String {
+        match self.id_namespace {
+            IdNamespace::Prefix(ref prefix) => format!("{}-{}", prefix, name),
+            IdNamespace::Unprefixed | IdNamespace::ContentHash => name.to_string(),
+        }
+    }
+
+    /// the coordinate `v` as written to the svg,
+    /// rounded to `precision` decimal places in compact mode
+    pub fn round(&self, v: f32) -> f32 {
+        if !self.compact {
+            return v;
+        }
+        round_to(v, self.precision as i32)
+    }
+
     pub fn set_size(&mut self, text_width: f32, text_height: f32) {
         self.text_width = text_width;
         self.text_height = text_height;
@@ -32,6 +165,43 @@ impl Settings {
         self.stroke_width = self.stroke_width * scale;
     }
 
+    /// the number of cells this character occupies,
+    /// `None` for control characters
+    pub fn char_width(&self, ch: char) -> Option<usize> {
+        if self.cjk_width {
+            ch.width_cjk()
+        } else {
+            ch.width()
+        }
+    }
+
+    /// the number of cells an extended grapheme cluster occupies,
+    /// `None` for control characters
+    ///
+    /// A cluster takes at least 1 cell, so combining marks without a base
+    /// character are still visible. Emoji presentation sequences
+    /// and flags are always 2 cells wide.
+    pub fn cluster_width(&self, cluster: &str) -> Option<usize> {
+        let mut width = 0;
+        for (i, ch) in cluster.chars().enumerate() {
+            if i == 0 {
+                width = self.char_width(ch)?;
+            } else if ch == '\u{FE0F}' || is_regional_indicator(ch) {
+                width = 2;
+            } else {
+                width = width.max(self.char_width(ch).unwrap_or(0));
+            }
+        }
+        Some(width.max(1).min(2))
+    }
+
+    /// the number of cells this string occupies
+    pub fn str_width(&self, s: &str) -> usize {
+        s.graphemes(true)
+            .map(|cluster| self.cluster_width(cluster).unwrap_or(0))
+            .sum()
+    }
+
 }
 
 impl Default for Settings {
@@ -45,7 +215,25 @@ impl Default for Settings {
             font_size: 14.0,
             stroke_width: 2.0,
             stroke_color: "black".into(),
-            background_color: "white".into(),
+            background_color: Some("white".into()),
+            tab_width: 8,
+            cjk_width: false,
+            source_map: false,
+            id_namespace: IdNamespace::Unprefixed,
+            inline_markers: false,
+            merge_paths: false,
+            rect_shapes: false,
+            compact: false,
+            precision: 2,
+            sizing: Sizing::Fixed,
+            crop: None,
+            #[cfg(feature = "text-outlines")]
+            text_outlines: None,
         }
     }
 }
+
+/// the regional indicators used in pairs to make up flags
+fn is_regional_indicator(ch: char) -> bool {
+    ch >= '\u{1F1E6}' && ch <= '\u{1F1FF}'
+}
@@ -2,21 +2,50 @@ use std;
 use svg::node::element::Circle as SvgCircle;
 use svg::node::element::Line as SvgLine;
 use svg::node::element::Path as SvgPath;
+use svg::node::element::Polygon as SvgPolygon;
+use svg::node::element::Rectangle as SvgRect;
 use svg::node::element::Text as SvgText;
+use svg::node::Value;
 
 pub enum SvgElement {
     Circle(SvgCircle),
     Line(SvgLine),
     Path(SvgPath),
+    Polygon(SvgPolygon),
+    Rect(SvgRect),
     Text(SvgText),
 }
 
+impl SvgElement {
+    /// set the attribute `name` of the element
+    pub fn set<V: Into<Value>>(self, name: &str, value: V) -> SvgElement {
+        match self {
+            SvgElement::Circle(circle) => SvgElement::Circle(circle.set(name, value)),
+            SvgElement::Line(line) => SvgElement::Line(line.set(name, value)),
+            SvgElement::Path(path) => SvgElement::Path(path.set(name, value)),
+            SvgElement::Polygon(polygon) => SvgElement::Polygon(polygon.set(name, value)),
+            SvgElement::Rect(rect) => SvgElement::Rect(rect.set(name, value)),
+            SvgElement::Text(text) => SvgElement::Text(text.set(name, value)),
+        }
+    }
+}
+
+/// the class all of `classes` have in common, to be set on their parent `<g>` instead
+pub fn shared_class(classes: &[&'static str]) -> Option<&'static str> {
+    match classes.split_first() {
+        Some((first, rest)) if !rest.is_empty() && rest.iter().all(|class| class == first) => Some(first),
+        _ => None,
+    }
+}
+
 impl std::fmt::Debug for SvgElement {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         match *self {
             SvgElement::Circle(ref c) => writeln!(fmt, "{}", c.to_string()),
             SvgElement::Line(ref l) => writeln!(fmt, "{}", l.to_string()),
             SvgElement::Path(ref p) => writeln!(fmt, "{}", p.to_string()),
+            SvgElement::Polygon(ref p) => writeln!(fmt, "{}", p.to_string()),
+            SvgElement::Rect(ref r) => writeln!(fmt, "{}", r.to_string()),
             SvgElement::Text(ref t) => writeln!(fmt, "{}", t.to_string()),
         }
     }
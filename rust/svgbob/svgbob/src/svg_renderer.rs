@@ -0,0 +1,488 @@
+use svg::Node;
+use svg::node::element::SVG;
+use svg::node::element::{
+    Definitions,
+    Marker,
+    Rectangle as SvgRect,
+    Style,
+    Circle as SvgCircle,
+    Polygon as SvgPolygon,
+    Group,
+};
+use element::{ArcSegment, Cells, Element, Feature, Stroke};
+use graph::{connectors, find_rects, Rect};
+use grid::{svg_escape, ViewBox};
+use loc::Loc;
+use merge::merge_paths;
+use point::Point;
+use renderer::{MarkerEnd, Renderer};
+use settings::{IdNamespace, Settings, Sizing};
+use svg_element::{shared_class, SvgElement};
+
+/// The svg writer of `Grid::get_svg`.
+///
+/// The document has the definitions of the markers, the stylesheet and the backdrop,
+/// followed by a `<g>` for each group of connected elements
+#[derive(Default)]
+pub struct SvgRenderer {
+    settings: Settings,
+    /// the document up to the groups
+    svg: Option<SVG>,
+    /// the area of the grid shown in the svg
+    pub(crate) view_box: ViewBox,
+    /// the elements of the group being drawn, converted to svg at the end of the group
+    group: Vec<Element>,
+    /// the groups drawn so far
+    pub(crate) groups: Vec<Group>,
+    /// the groups held back until the rectangles of the whole diagram are found,
+    /// when drawing them as `<rect>`
+    held: Vec<Vec<Element>>,
+}
+
+impl SvgRenderer {
+    pub fn new() -> SvgRenderer {
+        SvgRenderer::default()
+    }
+
+    /// the svg nodes of the elements of a group,
+    /// with the class they all have set on the `<g>` instead
+    fn get_svg_nodes(&self, group: Vec<Element>) -> (Option<&'static str>, Vec<SvgElement>) {
+        let settings = &self.settings;
+        if settings.merge_paths {
+            return merge_paths(group, settings);
+        }
+        let shapes: Vec<Vec<SvgElement>> = if settings.inline_markers {
+            group.iter().map(|elem| elem.get_feature_shapes(settings)).collect()
+        } else {
+            vec![]
+        };
+        // the shapes drawn in place of the markers have classes of their own
+        let group_class = if settings.compact && shapes.iter().all(|shapes| shapes.is_empty()) {
+            let classes: Vec<&'static str> = group.iter().map(|elem| elem.class()).collect();
+            shared_class(&classes)
+        } else {
+            None
+        };
+        let mut svg_group = vec![];
+        let mut shapes = shapes.into_iter();
+        for elem in group{
+            let element: SvgElement = elem.to_svg_in_group(settings, group_class);
+            svg_group.push(element);
+            svg_group.extend(shapes.next().unwrap_or_default());
+        }
+        (group_class, svg_group)
+    }
+
+    /// add a `<g>` of the nodes
+    fn push_group(&mut self, group_class: Option<&'static str>, nodes: Vec<SvgElement>) {
+        let mut svg_group = Group::new();
+        if let Some(class) = group_class {
+            svg_group.assign("class", class);
+        }
+        for node in nodes{
+            match node {
+                SvgElement::Circle(circle) => {
+                    svg_group.append(circle);
+                }
+                SvgElement::Line(line) => {
+                    svg_group.append(line);
+                }
+                SvgElement::Path(path) => {
+                    svg_group.append(path);
+                }
+                SvgElement::Polygon(polygon) => {
+                    svg_group.append(polygon);
+                }
+                SvgElement::Rect(rect) => {
+                    svg_group.append(rect);
+                }
+                SvgElement::Text(text) => {
+                    svg_group.append(text);
+                }
+            }
+        }
+        self.groups.push(svg_group);
+    }
+
+    /// draw the held back groups: the closed rectangles found in all of them
+    /// come first as a `<g>` each, followed by the groups without the sides of the rectangles
+    pub(crate) fn finish_groups(&mut self) {
+        let held = self.held.split_off(0);
+        let elements: Vec<Element> = held.iter().flat_map(|group| group.iter().cloned()).collect();
+        let rects = find_shape_rects(&elements);
+        for rect in &rects {
+            let node = rect_node(rect, &self.settings);
+            self.push_group(None, vec![node]);
+        }
+        for group in held {
+            let mut rest = vec![];
+            for elm in group {
+                match elm {
+                    Element::Line(..) | Element::Arc(..) => rest.extend(connectors(&[elm], &rects)),
+                    _ => rest.push(elm),
+                }
+            }
+            if !rest.is_empty() {
+                let (group_class, nodes) = self.get_svg_nodes(rest);
+                self.push_group(group_class, nodes);
+            }
+        }
+    }
+}
+
+/// the closed rectangles of solid lines that have the same radius at every corner
+fn find_shape_rects(elements: &[Element]) -> Vec<Rect> {
+    let solid: Vec<Element> = elements
+        .iter()
+        .filter(|elm| match **elm {
+            Element::Line(_, _, Stroke::Solid, ..) | Element::Arc(_, _, _, _, _, Stroke::Solid, ..) => true,
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    find_rects(&solid)
+        .into_iter()
+        .filter(|rect| rect.radii.iter().all(|&r| r == rect.radii[0]))
+        .collect()
+}
+
+/// the `<rect>` of a closed rectangle
+fn rect_node(rect: &Rect, settings: &Settings) -> SvgElement {
+    let mut svg_rect = SvgRect::new()
+        .set("x", settings.round(rect.x0))
+        .set("y", settings.round(rect.y0))
+        .set("width", settings.round(rect.x1 - rect.x0))
+        .set("height", settings.round(rect.y1 - rect.y0));
+    let r = rect.radii[0];
+    if r > 0.0 {
+        svg_rect.assign("rx", settings.round(r));
+        svg_rect.assign("ry", settings.round(r));
+    }
+    svg_rect.assign("class", "fg_stroke no_fill");
+    if settings.source_map {
+        // the cells the corners are in
+        let loc = |x: f32, y: f32| Loc::new((x / settings.text_width) as i32, (y / settings.text_height) as i32);
+        let cells = Cells::new(&loc(rect.x0, rect.y0), &loc(rect.x1, rect.y1));
+        if let Some(cells) = cells.to_attribute() {
+            svg_rect.assign("data-cells", cells);
+        }
+    }
+    SvgElement::Rect(svg_rect)
+}
+
+impl Renderer for SvgRenderer {
+    type Output = SVG;
+
+    fn begin_document(&mut self, view_box: &ViewBox, settings: &Settings) {
+        self.settings = settings.clone();
+        self.view_box = view_box.clone();
+        let (width, height) = (view_box.width, view_box.height);
+        let mut svg = SVG::new();
+
+        if let Some(ref id) = settings.id {
+            svg.assign("id", id.to_owned());
+        }
+        // the style sheet is scoped to the class of the namespace
+        let classes: Vec<&str> = settings.class.iter().map(|class| class.as_str()).chain(scope(settings)).collect();
+        if !classes.is_empty() {
+            svg.assign("class", classes.join(" "));
+        }
+        svg.assign("font-size", settings.round(settings.font_size));
+        svg.assign("font-family", settings.font_family.to_owned());
+        match settings.sizing {
+            Sizing::Fixed => {
+                svg.assign("width", width);
+                svg.assign("height", height);
+                // the cropped drawing is shown without moving the elements
+                if view_box.x != 0.0 || view_box.y != 0.0 {
+                    svg.assign("viewBox", view_box.to_string());
+                }
+            }
+            Sizing::ViewBox => {
+                svg.assign("viewBox", view_box.to_string());
+            }
+            Sizing::ViewBoxMaxWidth => {
+                svg.assign("viewBox", view_box.to_string());
+                svg.assign("style", format!("max-width: {}px", width));
+            }
+        }
+
+
+        if !settings.inline_markers {
+            svg.append(get_defs(settings));
+        }
+        svg.append(get_styles(settings));
+
+
+        if settings.background_color.is_some() {
+            let rect = SvgRect::new()
+                .set("x", view_box.x)
+                .set("y", view_box.y)
+                .set("class","backdrop")
+                .set("width", width)
+                .set("height", height);
+            svg.append(rect);
+        }
+        self.svg = Some(svg);
+    }
+
+    fn begin_group(&mut self) {
+        self.group.clear();
+    }
+
+    fn end_group(&mut self) {
+        let group = self.group.split_off(0);
+        if self.settings.rect_shapes {
+            self.held.push(group);
+            return;
+        }
+        let (group_class, nodes) = self.get_svg_nodes(group);
+        self.push_group(group_class, nodes);
+    }
+
+    fn line(&mut self, start: &Point, end: &Point, stroke: &Stroke, cells: &Cells) {
+        self.group.push(Element::Line(
+            start.clone(), end.clone(), stroke.clone(), Feature::Nothing, Feature::Nothing, cells.clone(),
+        ));
+    }
+
+    fn arc(&mut self, arc: &ArcSegment, stroke: &Stroke, cells: &Cells) {
+        self.group.push(Element::Arc(
+            arc.start.clone(), arc.end.clone(), arc.radius, arc.arc_flag.clone(), arc.sweep,
+            stroke.clone(), Feature::Nothing, Feature::Nothing, cells.clone(),
+        ));
+    }
+
+    fn circle(&mut self, center: &Point, radius: f32, cells: &Cells) {
+        self.group.push(Element::Circle(center.clone(), radius, cells.clone()));
+    }
+
+    fn text(&mut self, loc: &Loc, text: &str, cells: &Cells) {
+        self.group.push(Element::Text(loc.clone(), svg_escape(text), cells.clone()));
+    }
+
+    /// the markers are referenced from the line or arc, or drawn after it as shapes
+    fn marker(&mut self, feature: &Feature, end: MarkerEnd, _at: &Point, _direction: (f32, f32)) {
+        if let Some(elm) = self.group.last_mut() {
+            elm.set_feature(end, feature.clone());
+        }
+    }
+
+    fn end_document(mut self) -> SVG {
+        self.finish_groups();
+        let mut svg = self.svg.unwrap_or_else(SVG::new);
+        for group in self.groups {
+            svg.append(group);
+        }
+        svg
+    }
+}
+
+fn get_defs(settings: &Settings) -> Definitions {
+    let mut defs = Definitions::new();
+    defs.append(arrow_marker(settings));
+    defs.append(clear_arrow_marker(settings));
+    defs.append(circle_marker(settings));
+    defs.append(square_marker(settings));
+    defs.append(open_circle_marker(settings));
+    defs.append(big_open_circle_marker(settings));
+    defs
+}
+
+/// the class the rules of the style sheet are scoped to,
+/// so the svgs of different namespaces on the same page keep their own colors
+fn scope(settings: &Settings) -> Option<&str> {
+    match settings.id_namespace {
+        IdNamespace::Prefix(ref prefix) => Some(prefix.as_str()),
+        IdNamespace::Unprefixed | IdNamespace::ContentHash => None,
+    }
+}
+
+fn get_styles(settings: &Settings) -> Style {
+    let style = format!(
+        r#"
+{scope}rect.backdrop {{
+    fill: {background_color};
+}}
+
+{scope}.fg_fill {{
+    fill: {stroke_color};
+}}
+
+
+{scope}.bg_fill {{
+    fill: {background_color};
+}}
+
+{scope}.fg_stroke {{
+    stroke: {stroke_color};
+    stroke-width: {stroke_width};
+}}
+
+{scope}.no_fill {{
+    fill: none;
+}}
+
+{scope}.dashed {{
+    stroke-dasharray: 5;
+}}
+
+
+{scope}text{{
+    fill: {stroke_color};
+}}
+
+{scope}line, {scope}path {{
+    stroke-linecap: round;
+    stroke-linejoin: miter;
+    stroke-opacity: 1;
+    fill-opacity: 1;
+}}
+
+
+
+
+{scope}tspan.head{{
+    fill: none;
+    stroke: none;
+}}
+    "#,
+        scope = scope(settings).map_or(String::new(), |scope| format!(".{} ", scope)),
+        stroke_width = settings.round(settings.stroke_width),
+        stroke_color = &settings.stroke_color,
+        background_color = settings.background_color.as_ref().map_or("none", |color| color.as_str()),
+    );
+    let style = if settings.compact { minify_css(&style) } else { style };
+    Style::new(style)
+        .set("type", "text/css")
+}
+
+/// the stylesheet without the whitespace and the `;` at the end of each rule
+fn minify_css(css: &str) -> String {
+    let mut minified = String::with_capacity(css.len());
+    for word in css.split_whitespace() {
+        let joined = minified.ends_with(|c| "{};:,".contains(c)) || word.starts_with(|c| "{};:,".contains(c));
+        if !minified.is_empty() && !joined {
+            minified.push(' ');
+        }
+        minified.push_str(word);
+    }
+    minified.replace(";}", "}")
+}
+
+fn arrow_marker(settings: &Settings) -> Marker {
+    let mut marker = Marker::new()
+        .set("id", settings.id("triangle"))
+        .set("viewBox", "0 0 8 6")
+        .set("refX", 4)
+        .set("refY", 3)
+        .set("orient", "auto")
+        .set("markerWidth", 8)
+        .set("markerHeight", 8);
+
+    let path = SvgPolygon::new()
+        .set("points", "0,0 0,6 8,3 0,0")
+        .set("class", "fg_fill");
+
+    marker.append(path);
+    marker
+}
+
+fn clear_arrow_marker(settings: &Settings) -> Marker {
+    let mut marker = Marker::new()
+        .set("id", settings.id("clear_triangle"))
+        .set("viewBox", "0 0 20 14")
+        .set("refX", 1)
+        .set("refY", 7)
+        .set("orient", "auto")
+        .set("markerWidth", 10)
+        .set("markerHeight", 10);
+
+    let path = SvgPolygon::new()
+        .set("points", "2,2 2,12 18,7 2,2")
+        .set("class", "bg_fill fg_stroke");
+
+    marker.append(path);
+    marker
+}
+
+
+///   <marker id="dot" viewBox="0 0 10 10" refX="5" refY="5"
+///        markerWidth="5" markerHeight="5">
+///      <circle cx="5" cy="5" r="5" fill="red" />
+///    </marker>
+fn circle_marker(settings: &Settings) -> Marker {
+    let mut marker = Marker::new()
+        .set("id", settings.id("circle"))
+        .set("viewBox", "0 0 20 20")
+        .set("refX", 10)
+        .set("refY", 10)
+        .set("orient", "auto")
+        .set("markerWidth", 5)
+        .set("markerHeight", 5);
+
+    let circle = SvgCircle::new()
+        .set("cx",10)
+        .set("cy",10)
+        .set("r",8)
+        .set("class", "fg_fill");
+    marker.append(circle);
+    marker
+}
+
+fn square_marker(settings: &Settings) -> Marker {
+    let mut marker = Marker::new()
+        .set("id", settings.id("square"))
+        .set("viewBox", "0 0 20 20")
+        .set("refX", 10)
+        .set("refY", 10)
+        .set("orient", "auto")
+        .set("markerWidth", 5)
+        .set("markerHeight", 5);
+
+    let square = SvgRect::new()
+        .set("x",0)
+        .set("y",0)
+        .set("width",20)
+        .set("height",20)
+        .set("class", "fg_fill");
+    marker.append(square);
+    marker
+}
+
+fn open_circle_marker(settings: &Settings) -> Marker {
+    let mut marker = Marker::new()
+        .set("id", settings.id("open_circle"))
+        .set("viewBox", "0 0 20 20")
+        .set("refX", 10)
+        .set("refY", 10)
+        .set("orient", "auto")
+        .set("markerWidth", 10)
+        .set("markerHeight", 10);
+
+    let circle = SvgCircle::new()
+        .set("cx",10)
+        .set("cy",10)
+        .set("r",4)
+        .set("class", "bg_fill fg_stroke");
+    marker.append(circle);
+    marker
+}
+fn big_open_circle_marker(settings: &Settings) -> Marker {
+    let mut marker = Marker::new()
+        .set("id", settings.id("big_open_circle"))
+        .set("viewBox", "0 0 40 40")
+        .set("refX", 20)
+        .set("refY", 20)
+        .set("orient", "auto")
+        .set("markerWidth", 20)
+        .set("markerHeight", 20);
+
+    let circle = SvgCircle::new()
+        .set("cx",20)
+        .set("cy",20)
+        .set("r",6)
+        .set("class", "bg_fill fg_stroke");
+    marker.append(circle);
+    marker
+}
@@ -0,0 +1,232 @@
+use std::fmt::Write;
+use element::{round_to, ArcSegment, Cells, Feature, Stroke};
+use grid::{Grid, ViewBox};
+use loc::Loc;
+use point::Point;
+use renderer::{MarkerEnd, Renderer};
+use settings::Settings;
+
+/// one svg unit is one css pixel, 3/4 of a point
+const PT_PER_UNIT: f32 = 0.75;
+
+impl Grid {
+    /// get the diagram as a `tikzpicture` for LaTeX documents,
+    /// the arrow tips need `\usetikzlibrary{arrows.meta}` in the preamble.
+    ///
+    /// The coordinates are in cells of the grid,
+    /// the x and y unit vectors are the size of a cell
+    pub fn get_tikz(&self) -> String {
+        self.render(TikzRenderer::default())
+    }
+}
+
+/// the tikz writer
+#[derive(Default)]
+struct TikzRenderer {
+    tikz: String,
+    settings: Settings,
+    /// the `\draw` of the last line or arc, written once its arrow tips are known
+    pending: Option<Draw>,
+}
+
+/// a `\draw` path with its options
+struct Draw {
+    path: String,
+    dashed: bool,
+    start_tip: &'static str,
+    end_tip: &'static str,
+}
+
+impl TikzRenderer {
+    /// `v` rounded to 3 decimal places, or in compact mode to one more than `Settings::precision`
+    /// as the coordinates are in cells, which are about ten svg units
+    fn num(&self, v: f32) -> f32 {
+        if self.settings.compact {
+            round_to(v, self.settings.precision as i32 + 1)
+        } else {
+            round_to(v, 3)
+        }
+    }
+
+    /// the angle in degrees rounded to 2 decimal places, or in compact mode to `Settings::precision`
+    fn angle(&self, degrees: f32) -> f32 {
+        if self.settings.compact {
+            round_to(degrees, self.settings.precision as i32)
+        } else {
+            round_to(degrees, 2)
+        }
+    }
+
+    /// the svg coordinates in cells
+    fn at(&self, p: &Point) -> String {
+        format!("({},{})", self.num(p.x / self.settings.text_width), self.num(p.y / self.settings.text_height))
+    }
+
+    /// the radii in cells of the x and y direction
+    fn radius(&self, r: f32) -> String {
+        format!(
+            "x radius={}, y radius={}",
+            self.num(r / self.settings.text_width),
+            self.num(r / self.settings.text_height)
+        )
+    }
+
+    fn draw(&mut self, path: String, stroke: &Stroke) {
+        self.flush();
+        self.pending = Some(Draw {
+            path: path,
+            dashed: *stroke == Stroke::Dashed,
+            start_tip: "",
+            end_tip: "",
+        });
+    }
+
+    /// write the pending `\draw`
+    fn flush(&mut self) {
+        if let Some(draw) = self.pending.take() {
+            let mut options = vec![];
+            if draw.dashed {
+                options.push("dashed".to_string());
+            }
+            if !draw.start_tip.is_empty() || !draw.end_tip.is_empty() {
+                options.push(format!("{}-{}", draw.start_tip, draw.end_tip));
+            }
+            let options = if options.is_empty() {
+                String::new()
+            } else {
+                format!("[{}]", options.join(", "))
+            };
+            writeln!(self.tikz, "\\draw{} {};", options, draw.path).expect("writing to a string");
+        }
+    }
+}
+
+impl Renderer for TikzRenderer {
+    type Output = String;
+
+    fn begin_document(&mut self, _view_box: &ViewBox, settings: &Settings) {
+        self.settings = settings.clone();
+        writeln!(self.tikz, "% \\usetikzlibrary{{arrows.meta}}").expect("writing to a string");
+        writeln!(
+            self.tikz,
+            "\\begin{{tikzpicture}}[x={}pt, y=-{}pt, line width={}pt, line cap=round,\n    \
+             every node/.style={{font=\\ttfamily, inner sep=0, anchor=base west}}]",
+            self.num(settings.text_width * PT_PER_UNIT),
+            self.num(settings.text_height * PT_PER_UNIT),
+            self.num(settings.stroke_width * PT_PER_UNIT)
+        ).expect("writing to a string");
+    }
+
+    fn line(&mut self, start: &Point, end: &Point, stroke: &Stroke, _cells: &Cells) {
+        let path = format!("{} -- {}", self.at(start), self.at(end));
+        self.draw(path, stroke);
+    }
+
+    fn arc(&mut self, arc: &ArcSegment, stroke: &Stroke, _cells: &Cells) {
+        let geometry = arc.geometry();
+        let a0 = geometry.start_angle.to_degrees();
+        // a sweep goes in the direction of increasing angles,
+        // the same as in tikz since both the angles and the y axis are flipped
+        let a1 = (geometry.start_angle + geometry.sweep_angle).to_degrees();
+        let path = format!(
+            "{} arc[start angle={}, end angle={}, {}]",
+            self.at(&arc.start),
+            self.angle(a0),
+            self.angle(a1),
+            self.radius(geometry.radius)
+        );
+        self.draw(path, stroke);
+    }
+
+    fn circle(&mut self, center: &Point, radius: f32, _cells: &Cells) {
+        let path = format!("{} ellipse[{}]", self.at(center), self.radius(radius));
+        self.draw(path, &Stroke::Solid);
+    }
+
+    fn text(&mut self, loc: &Loc, text: &str, _cells: &Cells) {
+        self.flush();
+        // on the same baseline as the svg text
+        let baseline = Point::new(
+            loc.x as f32 * self.settings.text_width,
+            loc.y as f32 * self.settings.text_height + self.settings.text_height * 3.0 / 4.0,
+        );
+        let node = format!("\\node at {} {{{}}};", self.at(&baseline), latex_escape(text));
+        writeln!(self.tikz, "{}", node).expect("writing to a string");
+    }
+
+    fn marker(&mut self, feature: &Feature, end: MarkerEnd, _at: &Point, _direction: (f32, f32)) {
+        if let Some(ref mut draw) = self.pending {
+            match end {
+                MarkerEnd::Start => draw.start_tip = arrow_tip(feature),
+                MarkerEnd::End => draw.end_tip = arrow_tip(feature),
+            }
+        }
+    }
+
+    fn end_document(mut self) -> String {
+        self.flush();
+        self.tikz.push_str("\\end{tikzpicture}\n");
+        self.tikz
+    }
+}
+
+/// the arrow tip of the `arrows.meta` library drawn for the feature
+fn arrow_tip(feature: &Feature) -> &'static str {
+    match *feature {
+        Feature::Arrow => "{Latex}",
+        Feature::ClearArrow => "{Latex[open]}",
+        Feature::Circle => "{Circle}",
+        Feature::Square => "{Square}",
+        Feature::OpenCircle => "{Circle[open]}",
+        Feature::BigOpenCircle => "{Circle[open, scale=1.5]}",
+        Feature::Nothing => "",
+    }
+}
+
+/// escape the characters that have a meaning in LaTeX,
+/// spaces are kept as they are to line up with the cells
+fn latex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '%' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            ' ' => escaped.push('~'),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tikz() {
+        let tikz = Grid::from_str(".--> 100%\n'", &Settings::default()).get_tikz();
+        assert!(tikz.starts_with("% \\usetikzlibrary{arrows.meta}\n\\begin{tikzpicture}[x=6pt, y=-12pt,"));
+        assert!(tikz.contains("\\draw[-{Latex}] (1,0.5) -- (3.5,0.5);"));
+        assert!(tikz.contains("\\draw (1,0.5) arc[start angle=-90, end angle=-180, x radius=0.5, y radius=0.25];"));
+        assert!(tikz.contains("\\node at (5,0.75) {100\\%};"));
+        assert!(tikz.ends_with("\\end{tikzpicture}\n"));
+    }
+
+    #[test]
+    fn test_tikz_precision() {
+        let mut settings = Settings::default();
+        settings.stroke_width = 0.123_456;
+        settings.precision = 0;
+        // the precision only applies in compact mode
+        assert!(Grid::from_str("-", &settings).get_tikz().contains("line width=0.093pt"));
+        settings.compact = true;
+        assert!(Grid::from_str("-", &settings).get_tikz().contains("line width=0.1pt"));
+        settings.precision = 3;
+        assert!(Grid::from_str("-", &settings).get_tikz().contains("line width=0.0926pt"));
+    }
+}
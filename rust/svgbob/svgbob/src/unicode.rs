@@ -0,0 +1,172 @@
+use block::Block;
+use focus_char::FocusChar;
+use fragments::Fragment;
+use grid::{xml_unescape, Grid};
+use loc::Loc;
+use point_block::PointBlock;
+
+impl Grid {
+    /// get the diagram as text with the ascii drawing characters
+    /// replaced by unicode box drawing characters,
+    /// the characters used as text are kept as they are.
+    ///
+    /// The directive lines at the top of the input are left out
+    pub fn get_unicode(&self) -> String {
+        let mut rows: Vec<Vec<String>> = Vec::with_capacity(self.rows());
+        for y in 0..self.rows() {
+            let mut row = vec![];
+            let mut loc = Loc::new(0, y as i32);
+            while let Some(cell) = self.get(&loc) {
+                let ch = match box_drawing(&self.get_focuschar(&loc)) {
+                    Some(ch) => ch.to_string(),
+                    // the filler cell of a wide character is left empty
+                    None => cell.trim_matches('\0').to_string(),
+                };
+                row.push(ch);
+                loc = loc.right();
+            }
+            rows.push(row);
+        }
+        // the escaped texts are put back in the cells they were blanked out of
+        for &(x, y, ref text) in self.escaped_texts() {
+            let quoted = format!("\"{}\"", xml_unescape(text));
+            let width = self.settings.str_width(&quoted);
+            let row = &mut rows[y];
+            for cell in row.iter_mut().skip(x).take(width) {
+                cell.clear();
+            }
+            if x < row.len() {
+                row[x] = quoted;
+            }
+        }
+        let mut unicode = String::new();
+        for row in rows {
+            unicode.push_str(row.concat().trim_end());
+            unicode.push('\n');
+        }
+        unicode
+    }
+}
+
+/// the directions a cell connects to
+#[derive(Default)]
+struct Connect {
+    top: bool,
+    bottom: bool,
+    left: bool,
+    right: bool,
+    dashed: bool,
+    rounded: bool,
+}
+
+impl Connect {
+    /// mark the side `pb` is on, false if it is not on the middle of a side of the cell
+    fn side(&mut self, pb: &PointBlock) -> bool {
+        if pb.location.is_some() || pb.adjust_x != 0.0 || pb.adjust_y != 0.0 {
+            return false;
+        }
+        match pb.block {
+            Block::C => self.top = true,
+            Block::W => self.bottom = true,
+            Block::K => self.left = true,
+            Block::O => self.right = true,
+            // the corners connect diagonally
+            Block::A | Block::E | Block::U | Block::Y => return false,
+            _ => (),
+        }
+        true
+    }
+}
+
+/// the unicode character the drawing character is replaced with,
+/// None if it is used as text or it can not be drawn with one
+fn box_drawing(focus_char: &FocusChar) -> Option<char> {
+    // `_` and `/` have no unicode counterpart in the cell, `~` are dashes
+    if !focus_char.any("-|+.,'`~:!<>^vV") {
+        return None;
+    }
+    let fragments = focus_char.get_fragments();
+    if fragments.is_empty() {
+        return None;
+    }
+    let mut connect = Connect::default();
+    for fragment in &fragments {
+        let drawn = match *fragment {
+            Fragment::Line(ref s, ref e)
+            | Fragment::ArrowLine(ref s, ref e)
+            | Fragment::StartArrowLine(ref s, ref e) => connect.side(s) && connect.side(e),
+            Fragment::DashedLine(ref s, ref e) => {
+                connect.dashed = true;
+                connect.side(s) && connect.side(e)
+            }
+            Fragment::Arc(ref s, ref e, _) => {
+                connect.rounded = true;
+                connect.side(s) && connect.side(e)
+            }
+            _ => false,
+        };
+        if !drawn {
+            return None;
+        }
+    }
+    let Connect {
+        top,
+        bottom,
+        left,
+        right,
+        dashed,
+        rounded,
+    } = connect;
+    if focus_char.is('>') {
+        return Some('→');
+    } else if focus_char.is('<') {
+        return Some('←');
+    } else if focus_char.is('^') {
+        return Some('↑');
+    } else if focus_char.any("vV") {
+        return Some('↓');
+    }
+    match (top, bottom, left, right) {
+        (false, false, true, true) if dashed => Some('╌'),
+        (false, false, true, true) => Some('─'),
+        (true, true, false, false) if dashed => Some('╎'),
+        (true, true, false, false) => Some('│'),
+        (false, true, false, true) if rounded => Some('╭'),
+        (false, true, false, true) => Some('┌'),
+        (false, true, true, false) if rounded => Some('╮'),
+        (false, true, true, false) => Some('┐'),
+        (true, false, false, true) if rounded => Some('╰'),
+        (true, false, false, true) => Some('└'),
+        (true, false, true, false) if rounded => Some('╯'),
+        (true, false, true, false) => Some('┘'),
+        (true, true, false, true) => Some('├'),
+        (true, true, true, false) => Some('┤'),
+        (false, true, true, true) => Some('┬'),
+        (true, false, true, true) => Some('┴'),
+        (true, true, true, true) => Some('┼'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use settings::Settings;
+
+    #[test]
+    fn test_unicode() {
+        let input = "+---+ .--. \"a-b\"\n|   +-+  | -->\n+---+ '--'";
+        let unicode = Grid::from_str(input, &Settings::default()).get_unicode();
+        assert_eq!(
+            "┌───┐ ╭──╮ \"a-b\"\n│   ├─┤  │ ──→\n└───┘ ╰──╯\n",
+            unicode
+        );
+    }
+
+    #[test]
+    fn test_unicode_text() {
+        let input = "well-known x^2 - - -";
+        let unicode = Grid::from_str(input, &Settings::default()).get_unicode();
+        assert_eq!("well-known x^2 ╌╌╌╌╌\n", unicode);
+    }
+}
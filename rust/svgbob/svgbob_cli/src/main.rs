@@ -28,7 +28,7 @@ fn main() {
             .short("o")
             .long("output")
             .takes_value(true)
-            .help("where to write svg output [default: STDOUT]"))
+            .help("where to write the output [default: STDOUT]"))
         .arg(Arg::with_name("font-family")
              .long("font-family")
              .takes_value(true)
@@ -41,10 +41,69 @@ fn main() {
              .long("stroke-width")
              .takes_value(true)
              .help("stroke width for all lines (default: 2)"))
+        .arg(Arg::with_name("tab-width")
+             .long("tab-width")
+             .takes_value(true)
+             .help("number of columns between tab stops, at most 32, 0 turns off the expansion and ignores tabs (default: 8)"))
+        .arg(Arg::with_name("cjk-width")
+             .long("cjk-width")
+             .help("treat East Asian ambiguous-width characters as 2 cells wide"))
+        .arg(Arg::with_name("id-prefix")
+             .long("id-prefix")
+             .takes_value(true)
+             .help("prefix of the marker ids, \"hash\" derives it from the diagram, keeping the ids of svgs embedded in the same html document apart"))
+        .arg(Arg::with_name("inline-markers")
+             .long("inline-markers")
+             .help("draw arrowheads and end points as polygons and circles instead of svg markers"))
+        .arg(Arg::with_name("merge-paths")
+             .long("merge-paths")
+             .help("draw the connected lines and arcs as one path, joined at the corners"))
+        .arg(Arg::with_name("rect-shapes")
+             .long("rect-shapes")
+             .help("draw the closed rectangles as one rect instead of their sides and corners"))
+        .arg(Arg::with_name("compact")
+             .long("compact")
+             .help("make the svg smaller by rounding the coordinates, minifying the stylesheet and sharing the classes of the elements of a group"))
+        .arg(Arg::with_name("precision")
+             .long("precision")
+             .takes_value(true)
+             .help("the decimal places of the coordinates with --compact (default: 2)"))
+        .arg(Arg::with_name("sizing")
+             .long("sizing")
+             .takes_value(true)
+             .possible_values(&["fixed", "viewbox", "max-width"])
+             .help("size the svg with width and height, a viewBox only, or a viewBox and a max-width style (default: fixed)"))
+        .arg(Arg::with_name("outline-text")
+             .long("outline-text")
+             .help("draw the text as path outlines of the bundled monospace font, making the svg independent of installed fonts"))
+        .arg(Arg::with_name("outline-font")
+             .long("outline-font")
+             .takes_value(true)
+             .help("draw the text as path outlines of this TTF or OTF font file"))
+        .arg(Arg::with_name("background")
+             .long("background")
+             .takes_value(true)
+             .help("background color, none leaves out the backdrop (default: white)"))
+        .arg(Arg::with_name("crop")
+             .long("crop")
+             .takes_value(true)
+             .help("crop the svg to the drawing with this much padding around it"))
+        .arg(Arg::with_name("source-map")
+             .long("source-map")
+             .help("tag each svg element with the cells it is made from in a data-cells attribute"))
         .arg(Arg::with_name("scale")
              .long("scale")
              .takes_value(true)
              .help("scale the entire svg (dimensions, font size, stroke width) by this factor (default: 1)"))
+        .arg(Arg::with_name("format")
+             .long("format")
+             .takes_value(true)
+             .possible_values(Format::NAMES)
+             .help("format of the output [default: from the extension of the output file, else svg]"))
+        .arg(Arg::with_name("dpi")
+             .long("dpi")
+             .takes_value(true)
+             .help("resolution of the png output, 96 is one pixel per svg unit (default: 96)"))
         .subcommand(SubCommand::with_name("build")
             .about("Batch convert files to svg.")
             .version("0.0.1")
@@ -114,28 +173,278 @@ fn main() {
         settings.stroke_width = stroke_width;
     }
 
-    if let Some(scale) = parse_value_of(&args, "scale") {
-        settings.scale(scale);
+    if let Some(tab_width) = parse_value_of::<usize>(&args, "tab-width") {
+        if tab_width > svgbob::MAX_TAB_WIDTH {
+            use std::io::Write;
+
+            writeln!(
+                &mut std::io::stderr(),
+                "warning: value `{}` for --tab-width is out of range, using `{}`",
+                tab_width,
+                svgbob::MAX_TAB_WIDTH
+            ).unwrap();
+        }
+        settings.tab_width = tab_width.min(svgbob::MAX_TAB_WIDTH);
     }
 
-    let g = Grid::from_str(&*bob, &settings);
-    let svg = g.get_svg();
+    if args.is_present("cjk-width") {
+        settings.cjk_width = true;
+    }
+
+    if let Some(prefix) = args.value_of("id-prefix") {
+        settings.id_namespace = match prefix {
+            "hash" => svgbob::IdNamespace::ContentHash,
+            prefix => match svgbob::IdNamespace::prefix(prefix) {
+                Ok(namespace) => namespace,
+                Err(e) => {
+                    use std::io::Write;
+
+                    writeln!(&mut std::io::stderr(), "Illegal value for argument id-prefix: {}", e).unwrap();
+                    exit(1);
+                }
+            },
+        };
+    }
+
+    if args.is_present("inline-markers") {
+        settings.inline_markers = true;
+    }
+
+    if args.is_present("merge-paths") {
+        settings.merge_paths = true;
+    }
 
-    if let Some(file) = args.value_of("output") {
-        if let Err(e) = svg::save(file, &svg) {
+    if args.is_present("rect-shapes") {
+        settings.rect_shapes = true;
+    }
+
+    if args.is_present("compact") {
+        settings.compact = true;
+    }
+
+    if let Some(precision) = parse_value_of(&args, "precision") {
+        settings.precision = precision;
+    }
+
+    if let Some(sizing) = parse_value_of(&args, "sizing") {
+        settings.sizing = sizing;
+    }
+
+    #[cfg(feature = "text-outlines")]
+    {
+        if let Some(file) = args.value_of("outline-font") {
+            match svgbob::OutlineFont::from_file(file) {
+                Ok(font) => settings.text_outlines = Some(font),
+                Err(e) => {
+                    use std::io::Write;
+
+                    writeln!(
+                        &mut std::io::stderr(),
+                        "Failed to read font file {}: {}",
+                        file,
+                        e
+                    ).unwrap();
+                    exit(1);
+                }
+            }
+        } else if args.is_present("outline-text") {
+            settings.text_outlines = Some(svgbob::OutlineFont::bundled());
+        }
+    }
+    #[cfg(not(feature = "text-outlines"))]
+    {
+        if args.is_present("outline-font") || args.is_present("outline-text") {
+            use std::io::Write;
+
+            writeln!(&mut std::io::stderr(), "svgbob was built without text outline support").unwrap();
+            exit(1);
+        }
+    }
+
+    if let Some(background) = args.value_of("background") {
+        settings.background_color = match background {
+            "none" => None,
+            // the color is written into the style sheet as it is
+            color if svgbob::is_color(color) => Some(color.to_string()),
+            color => {
+                use std::io::Write;
+
+                writeln!(&mut std::io::stderr(), "Illegal value for argument background: {} is not a color", color)
+                    .unwrap();
+                exit(1);
+            }
+        };
+    }
+
+    if let Some(padding) = parse_value_of::<f32>(&args, "crop") {
+        if !padding.is_finite() || padding < 0.0 {
             use std::io::Write;
-            use std::process::exit;
 
             writeln!(
                 &mut std::io::stderr(),
-                "Failed to write to output file {}: {}",
-                file,
-                e
+                "Illegal value for argument crop: {} is not a non-negative number",
+                padding
             ).unwrap();
-            exit(2);
+            exit(1);
+        }
+        settings.crop = Some(padding);
+    }
+
+    if args.is_present("source-map") {
+        settings.source_map = true;
+    }
+
+    if let Some(scale) = parse_value_of::<f32>(&args, "scale") {
+        // the sizes of the svg would be infinite or NaN
+        if !scale.is_finite() || scale <= 0.0 {
+            use std::io::Write;
+
+            writeln!(&mut std::io::stderr(), "Illegal value for argument scale: {} is not a positive number", scale)
+                .unwrap();
+            exit(1);
+        }
+        settings.scale(scale);
+    }
+
+    let (g, diagnostics) = Grid::try_from_str(&*bob, &settings);
+    for diagnostic in diagnostics {
+        use std::io::Write;
+        writeln!(&mut std::io::stderr(), "{}", diagnostic).unwrap();
+    }
+    let format = match parse_value_of(&args, "format") {
+        Some(format) => format,
+        None => match args.value_of("output").map(Format::from_path) {
+            Some(Ok(Some(format))) => format,
+            Some(Err(e)) => {
+                use std::io::Write;
+
+                writeln!(&mut std::io::stderr(), "Illegal value for argument output: {}", e).unwrap();
+                exit(1);
+            }
+            Some(Ok(None)) | None => Format::Svg,
+        },
+    };
+    if args.is_present("dpi") && !format.is_png() {
+        use std::io::Write;
+
+        writeln!(&mut std::io::stderr(), "warning: --dpi only applies to png output and is ignored").unwrap();
+    }
+
+    let output = match format {
+        Format::Svg => {
+            let svg = g.get_svg();
+            if args.value_of("output").is_none() {
+                println!("{}", svg);
+                return;
+            }
+            svg.to_string().into_bytes()
+        }
+        Format::Pdf => g.get_pdf(),
+        Format::Tikz => g.get_tikz().into_bytes(),
+        Format::Unicode => g.get_unicode().into_bytes(),
+        Format::Drawio => g.get_drawio().into_bytes(),
+        Format::Excalidraw => g.get_excalidraw().into_bytes(),
+        #[cfg(feature = "png")]
+        Format::Png => {
+            let dpi = parse_value_of(&args, "dpi").unwrap_or(svgbob::DEFAULT_DPI);
+            match g.get_png(dpi / svgbob::DEFAULT_DPI) {
+                Ok(png) => png,
+                Err(e) => {
+                    use std::io::Write;
+
+                    writeln!(&mut std::io::stderr(), "Failed to render png: {}", e).unwrap();
+                    exit(2);
+                }
+            }
+        }
+    };
+
+    let written = match args.value_of("output") {
+        Some(file) => fs::write(file, &output),
+        None => {
+            use std::io::Write;
+
+            std::io::stdout().write_all(&output)
+        }
+    };
+    if let Err(e) = written {
+        use std::io::Write;
+
+        writeln!(
+            &mut std::io::stderr(),
+            "Failed to write to output file {}: {}",
+            args.value_of("output").unwrap_or("STDOUT"),
+            e
+        ).unwrap();
+        exit(2);
+    }
+}
+
+/// the formats the diagram can be written in
+enum Format {
+    Svg,
+    Pdf,
+    Tikz,
+    Unicode,
+    Drawio,
+    Excalidraw,
+    #[cfg(feature = "png")]
+    Png,
+}
+
+impl Format {
+    #[cfg(not(feature = "png"))]
+    const NAMES: &'static [&'static str] = &["svg", "pdf", "tikz", "unicode", "drawio", "excalidraw"];
+    #[cfg(feature = "png")]
+    const NAMES: &'static [&'static str] = &["svg", "pdf", "tikz", "unicode", "drawio", "excalidraw", "png"];
+
+    /// the format of the file extension of `path`,
+    /// none if it has no extension or one that is not a format, those are written as svg
+    fn from_path(path: &str) -> Result<Option<Format>, String> {
+        let extension = match Path::new(path).extension() {
+            Some(extension) => extension.to_string_lossy().to_lowercase(),
+            None => return Ok(None),
+        };
+        match extension.as_str() {
+            "tex" => Ok(Some(Format::Tikz)),
+            "txt" => Ok(Some(Format::Unicode)),
+            // an error when svgbob is built without png support
+            "png" => "png".parse().map(Some),
+            extension => Ok(extension.parse().ok()),
+        }
+    }
+
+    #[cfg(feature = "png")]
+    fn is_png(&self) -> bool {
+        match *self {
+            Format::Png => true,
+            _ => false,
+        }
+    }
+
+    #[cfg(not(feature = "png"))]
+    fn is_png(&self) -> bool {
+        false
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "svg" => Ok(Format::Svg),
+            "pdf" => Ok(Format::Pdf),
+            "tikz" => Ok(Format::Tikz),
+            "unicode" => Ok(Format::Unicode),
+            "drawio" => Ok(Format::Drawio),
+            "excalidraw" => Ok(Format::Excalidraw),
+            #[cfg(feature = "png")]
+            "png" => Ok(Format::Png),
+            #[cfg(not(feature = "png"))]
+            "png" => Err("svgbob was built without png support".to_string()),
+            _ => Err(format!("unknown format `{}`, expecting one of: {}", s, Format::NAMES.join(", "))),
         }
-    } else {
-        println!("{}", svg);
     }
 }
 